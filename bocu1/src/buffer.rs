@@ -0,0 +1,185 @@
+//! Buffer-to-buffer conversion in the style of the encoding_rs crate.
+//!
+//! An Encoder or Decoder converts as much of a source buffer as fits into a
+//! caller-provided destination buffer, and reports how far it got in each.
+//! Neither ever allocates, and each carries the DeltaCoder state (and, for the
+//! Decoder, any incomplete code unit) from one call to the next, so a long
+//! text can be pushed through small fixed-size buffers a piece at a time.
+//!
+//! Output is only ever written in whole units: the Encoder never splits a
+//! char's 1-4 byte EncodedChunk across two destination buffers, and the
+//! Decoder never splits a char's UTF-8 or UTF-16 form. When the next unit
+//! doesn't fit, the call stops and reports OutputFull.
+
+use crate::delta_encoding::DeltaCoder;
+use crate::{DecodeError, IncrementalDecoder};
+
+/// Why an Encoder call returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoderResult {
+    /// All of the source was consumed.
+    InputEmpty,
+    /// The destination had no room for the next char's encoding.
+    OutputFull,
+}
+
+/// Why a Decoder call returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecoderResult {
+    /// All of the source was consumed.
+    InputEmpty,
+    /// The destination had no room for the next decoded char.
+    OutputFull,
+    /// The source was malformed. The bytes read so far include the malformed
+    /// code unit, which has been discarded; decoding may resume with the rest
+    /// of the source, though it will only make sense from the next sync point.
+    /// (A sync byte that cut the code unit short is left unread, since that
+    /// is where it makes sense from.)
+    Malformed(DecodeError),
+}
+
+pub struct Encoder {
+    state: DeltaCoder,
+}
+
+#[allow(clippy::new_without_default)]
+impl Encoder {
+    pub fn new() -> Self {
+        Self::with_state(DeltaCoder::new())
+    }
+
+    /// An encoder that carries on from `state`.
+    pub fn with_state(state: DeltaCoder) -> Self {
+        Self { state: state }
+    }
+
+    /// The coder state after everything encoded so far.
+    pub fn state(self: &Self) -> DeltaCoder {
+        self.state
+    }
+
+    /// Encode as much of `src` as fits into `dst`, returning why the call
+    /// stopped along with the number of bytes read from `src` and written to
+    /// `dst`.
+    ///
+    /// The encoder never holds back any output, so `last` makes no difference
+    /// to the result; it is accepted for symmetry with the decoder.
+    pub fn encode_from_utf8(
+        self: &mut Self,
+        src: &str,
+        dst: &mut [u8],
+        _last: bool,
+    ) -> (CoderResult, usize, usize) {
+        let mut written = 0;
+        for (read, c) in src.char_indices() {
+            // Encode against a copy of the state, only keeping it if the
+            // result fits.
+            let mut next = self.state;
+            let enc = next.encode_char(c);
+            let end = written + enc.count;
+            if end > dst.len() {
+                return (CoderResult::OutputFull, read, written);
+            }
+            dst[written..end].copy_from_slice(enc.as_slice());
+            written = end;
+            self.state = next;
+        }
+        (CoderResult::InputEmpty, src.len(), written)
+    }
+}
+
+pub struct Decoder {
+    inner: IncrementalDecoder,
+}
+
+#[allow(clippy::new_without_default)]
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            inner: IncrementalDecoder::new(),
+        }
+    }
+
+    /// The coder state after the last complete char decoded so far.
+    pub fn state(self: &Self) -> DeltaCoder {
+        self.inner.state()
+    }
+
+    /// Decode as much of `src` as fits into `dst` as UTF-8, returning why the
+    /// call stopped along with the number of bytes read from `src` and
+    /// written to `dst`. A code unit left incomplete at the end of `src` is
+    /// held over to the next call, unless `last` is set, in which case it is
+    /// reported as malformed.
+    pub fn decode_to_utf8(
+        self: &mut Self,
+        src: &[u8],
+        dst: &mut [u8],
+        last: bool,
+    ) -> (DecoderResult, usize, usize) {
+        self.decode_with(src, dst, last, |c, out| {
+            let n = c.len_utf8();
+            if n > out.len() {
+                return None;
+            }
+            c.encode_utf8(out);
+            Some(n)
+        })
+    }
+
+    /// As decode_to_utf8, but writing UTF-16 code units to `dst`.
+    pub fn decode_to_utf16(
+        self: &mut Self,
+        src: &[u8],
+        dst: &mut [u16],
+        last: bool,
+    ) -> (DecoderResult, usize, usize) {
+        self.decode_with(src, dst, last, |c, out| {
+            let n = c.len_utf16();
+            if n > out.len() {
+                return None;
+            }
+            c.encode_utf16(out);
+            Some(n)
+        })
+    }
+
+    // The shared loop of the two decode functions above; `put` writes a char
+    // to the front of the remaining output, returning how many units it used,
+    // or None if it doesn't fit.
+    fn decode_with<T, F>(
+        self: &mut Self,
+        src: &[u8],
+        dst: &mut [T],
+        last: bool,
+        put: F,
+    ) -> (DecoderResult, usize, usize)
+    where
+        F: Fn(char, &mut [T]) -> Option<usize>,
+    {
+        let mut written = 0;
+        for (read, &b) in src.iter().enumerate() {
+            // As in the encoder, step a copy of the decoder, so that a char
+            // that doesn't fit leaves its final byte unread.
+            let mut next = self.inner;
+            match next.push(b) {
+                Ok(None) => (),
+                Ok(Some(c)) => match put(c, &mut dst[written..]) {
+                    None => return (DecoderResult::OutputFull, read, written),
+                    Some(n) => written += n,
+                },
+                Err(e) => {
+                    let n = next.consumed() - self.inner.consumed();
+                    self.inner = next;
+                    return (DecoderResult::Malformed(e), read + n, written);
+                }
+            }
+            self.inner = next;
+        }
+        if last {
+            if let Err(e) = self.inner.finish() {
+                return (DecoderResult::Malformed(e), src.len(), written);
+            }
+        }
+        (DecoderResult::InputEmpty, src.len(), written)
+    }
+}
@@ -22,7 +22,8 @@
 //! There are a couple exceptions to this logic, see the outer loop below in
 //! encode_char which does the delta computation.
 
-use crate::*;
+use crate::variable_length_code;
+use crate::{DecodeError, DecodeErrorKind, EncodedChunk};
 
 /// Normalize a character (the previous character when delta-coding) to the
 /// middle of a script-specific block.
@@ -45,12 +46,17 @@ pub fn normalized_prev(curr: char) -> char {
             let cu32 = curr as u32;
             let guess_curr_block_start = cu32 & 0xffff_ff80_u32;
             let guess_curr_block_middle = guess_curr_block_start + 0x40;
-            let opt = ::std::char::from_u32(guess_curr_block_middle);
+            let opt = ::core::char::from_u32(guess_curr_block_middle);
             opt.expect("bug in BOCU1Encoder::normalized_prev")
         }
     }
 }
 
+/// The delta coder. Its only state is the normalized previous value, which
+/// is the same at the same point in the stream for encoder and decoder alike,
+/// so can be saved with state and restored with from_state, to carry on
+/// encoding (or decoding) where another coder left off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct DeltaCoder {
     prev: char,
 }
@@ -58,7 +64,7 @@ pub struct DeltaCoder {
 const INITIAL_PREVIOUS_STATE: char = '\u{40}';
 const ASCII_SP: char = '\u{20}';
 
-#[allow(clippy::new_without_default_derive)]
+#[allow(clippy::new_without_default)]
 impl DeltaCoder {
     pub fn new() -> Self {
         Self {
@@ -66,6 +72,29 @@ impl DeltaCoder {
         }
     }
 
+    /// The current (normalized) previous-value state.
+    pub fn state(self: &Self) -> char {
+        self.prev
+    }
+
+    /// A coder that resumes from a previous-value state returned by state,
+    /// or None if `prev` is not a state any coder could be in: that is, not
+    /// the initial state nor the normalized_prev of any char.
+    pub fn from_state(prev: char) -> Option<Self> {
+        // Every normalized value lies in the 128-char block of some char that
+        // maps to it, so there are at most 128 candidates to try.
+        let block_start = (prev as u32) & 0xffff_ff80_u32;
+        let reachable = prev == INITIAL_PREVIOUS_STATE
+            || (block_start..block_start + 0x80)
+                .filter_map(::core::char::from_u32)
+                .any(|c| c > ASCII_SP && normalized_prev(c) == prev);
+        if reachable {
+            Some(Self { prev: prev })
+        } else {
+            None
+        }
+    }
+
     /// For the most part, this is a simple delta encoder that just emits the
     /// stream of pairwise differences between characters.
     ///
@@ -103,8 +132,26 @@ impl DeltaCoder {
         }
     }
 
+    /// The number of bytes encode_char would produce for `curr`, updating the
+    /// state in the same way but skipping the work of producing the bytes.
+    #[inline]
+    pub fn encoded_char_len(self: &mut Self, curr: char) -> usize {
+        if curr <= ASCII_SP {
+            if curr != ASCII_SP {
+                self.prev = INITIAL_PREVIOUS_STATE;
+            }
+            1
+        } else {
+            let delta: i32 = (curr as i32) - (self.prev as i32);
+            self.prev = normalized_prev(curr);
+            variable_length_code::encoded_delta_len(delta)
+        }
+    }
+
     /// The decoder is just the inverse of the above, with some error handling
-    /// for malformed inputs.
+    /// for malformed inputs. Since the decoder only ever sees the slice
+    /// starting at the current code unit, any error it returns has offset 0;
+    /// callers that know where that slice began shift it accordingly.
     #[allow(clippy::cast_sign_loss)]
     pub fn decode_char<'a>(
         self: &mut Self,
@@ -113,6 +160,9 @@ impl DeltaCoder {
         assert!(!b.is_empty());
         let init = b[0];
         if init == variable_length_code::LEAD_BYTE_RESET {
+            // The reset byte decodes to nothing, but like the C0 controls it
+            // returns the previous-value state to its initial state.
+            self.prev = INITIAL_PREVIOUS_STATE;
             Ok((None, &b[1..]))
         } else if init <= variable_length_code::LEAD_BYTE_ASCII_SP {
             if init != variable_length_code::LEAD_BYTE_ASCII_SP {
@@ -120,11 +170,16 @@ impl DeltaCoder {
             }
             Ok((Some(init as char), &b[1..]))
         } else {
-            let (delta, rest) = variable_length_code::decode_delta(b)?;
+            let (delta, rest) = variable_length_code::decode_delta(b)
+                .map_err(|kind| DecodeError::new(kind, 0, self.prev))?;
             let candidate = (self.prev as i32) + delta;
-            let c = ::std::char::from_u32(candidate as u32);
+            let c = ::core::char::from_u32(candidate as u32);
             match c {
-                None => Err(DecodeError::CharDeltaOutOfRange(self.prev, delta)),
+                None => Err(DecodeError::new(
+                    DecodeErrorKind::CharDeltaOutOfRange(delta),
+                    0,
+                    self.prev,
+                )),
                 Some(ch) => {
                     self.prev = normalized_prev(ch);
                     Ok((Some(ch), rest))
@@ -0,0 +1,144 @@
+//! A push-based decoder, equivalent to the C reference implementation's
+//! decodeBocu1(Bocu1Rx*, uint8_t), that accepts input a byte (or a chunk of
+//! bytes) at a time and emits each char as soon as its code unit completes.
+//!
+//! Where the C decoder accumulates a partial delta and a remaining-byte count,
+//! this one simply holds on to the (at most 4) bytes of the current code unit
+//! and hands them to DeltaCoder::decode_char once the lead byte's length is
+//! reached. Trailing bytes are still checked as they arrive, so a bad one is
+//! reported immediately rather than when the code unit would have completed.
+
+use crate::delta_encoding::DeltaCoder;
+use crate::trailing_byte_selection;
+use crate::variable_length_code;
+use crate::{DecodeError, DecodeErrorKind};
+
+#[derive(Clone, Copy)]
+pub struct IncrementalDecoder {
+    state: DeltaCoder,
+    // The bytes of the code unit currently being received.
+    unit: [u8; 4],
+    len: usize,
+    need: usize,
+    // The number of bytes pushed so far.
+    consumed: usize,
+}
+
+#[allow(clippy::new_without_default)]
+impl IncrementalDecoder {
+    pub fn new() -> Self {
+        Self {
+            state: DeltaCoder::new(),
+            unit: [0; 4],
+            len: 0,
+            need: 0,
+            consumed: 0,
+        }
+    }
+
+    /// The coder state after the last complete char decoded so far (so not
+    /// counting any pending partial code unit).
+    pub fn state(self: &Self) -> DeltaCoder {
+        self.state
+    }
+
+    /// Whether the decoder is partway through a code unit, and so needs more
+    /// input before the stream can validly end.
+    pub fn is_pending(self: &Self) -> bool {
+        self.len != 0
+    }
+
+    /// The number of bytes pushed so far, not counting any left to be pushed
+    /// again after an error (see push).
+    pub fn consumed(self: &Self) -> usize {
+        self.consumed
+    }
+
+    /// Feed one byte to the decoder, returning the char it completes, if any.
+    ///
+    /// After an error, the partial code unit (including the offending byte)
+    /// is discarded and the decoder returns to its initial state, ready to
+    /// accept input from the next sync point. The exception is a sync byte
+    /// that cuts a code unit short: that is the next sync point, so it is
+    /// not consumed, and should be pushed again to decode the char it starts.
+    pub fn push(self: &mut Self, b: u8) -> Result<Option<char>, DecodeError> {
+        if self.len == 0 {
+            self.need = variable_length_code::code_unit_len(b);
+        } else if let Err(kind) = trailing_byte_selection::byte_to_trail(b) {
+            let e = self.fail(kind);
+            if !trailing_byte_selection::is_sync_byte(b) {
+                self.consumed += 1;
+            }
+            return Err(e);
+        }
+        self.unit[self.len] = b;
+        self.len += 1;
+        self.consumed += 1;
+        if self.len < self.need {
+            return Ok(None);
+        }
+
+        let start = self.consumed - self.len;
+        let unit = self.unit;
+        let len = self.len;
+        self.len = 0;
+        match self.state.decode_char(&unit[..len]) {
+            Ok((c, _)) => Ok(c),
+            Err(e) => {
+                self.state = DeltaCoder::new();
+                Err(e.offset_by(start))
+            }
+        }
+    }
+
+    /// Feed a chunk of bytes to the decoder, returning an iterator over the
+    /// chars (and errors) it produces. Any code unit left incomplete at the
+    /// end of the chunk is carried over to the next push.
+    pub fn push_slice<'a>(self: &'a mut Self, chunk: &'a [u8]) -> IncrementalIter<'a> {
+        IncrementalIter {
+            decoder: self,
+            input: chunk,
+        }
+    }
+
+    /// Signal the end of the input. This is an error if the stream ended
+    /// partway through a code unit; otherwise there is nothing to do, since
+    /// every char has already been emitted.
+    pub fn finish(self: &mut Self) -> Result<(), DecodeError> {
+        if self.is_pending() {
+            return Err(self.fail(DecodeErrorKind::TruncatedInput));
+        }
+        Ok(())
+    }
+
+    fn fail(self: &mut Self, kind: DecodeErrorKind) -> DecodeError {
+        let e = DecodeError::new(kind, self.consumed - self.len, self.state.state());
+        self.len = 0;
+        self.state = DeltaCoder::new();
+        e
+    }
+}
+
+pub struct IncrementalIter<'a> {
+    decoder: &'a mut IncrementalDecoder,
+    input: &'a [u8],
+}
+
+impl Iterator for IncrementalIter<'_> {
+    type Item = Result<char, DecodeError>;
+    fn next(self: &mut Self) -> Option<Result<char, DecodeError>> {
+        while let Some((&b, rest)) = self.input.split_first() {
+            let consumed = self.decoder.consumed;
+            let r = self.decoder.push(b);
+            if self.decoder.consumed != consumed {
+                self.input = rest;
+            }
+            match r {
+                Ok(None) => (),
+                Ok(Some(c)) => return Some(Ok(c)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
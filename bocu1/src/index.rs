@@ -0,0 +1,238 @@
+//! A checkpoint index for random access by char offset into long BOCU-1
+//! texts.
+//!
+//! Because the encoding is stateful, finding the millionth char otherwise
+//! means decoding everything before it (or everything since the last sync
+//! point, if there happens to be one nearby). A Bocu1Index records, every K
+//! chars, the byte offset of that char and the delta-coder state just before
+//! it, so that any char is at most K-1 chars' decoding away from a
+//! checkpoint.
+//!
+//! The index does not hold the text: each accessor is passed the same buffer
+//! the index was built from, which makes it easy to keep the two apart (say,
+//! the text mmapped and the index in a sidecar file, see to_bytes). Passing a
+//! different buffer gives meaningless results, though never undefined
+//! behaviour; a buffer of the wrong length is caught, and gives None.
+
+use crate::delta_encoding::DeltaCoder;
+use crate::iter::DecodeResultIter;
+use crate::DecodeError;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+use core::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Checkpoint {
+    byte_offset: usize,
+    state: DeltaCoder,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bocu1Index {
+    interval: usize,
+    char_len: usize,
+    byte_len: usize,
+    // checkpoints[i] is the position of char i * interval.
+    checkpoints: Vec<Checkpoint>,
+}
+
+/// Why Bocu1Index::from_bytes rejected its input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexFormatError {
+    /// The input was not the length its header implies.
+    WrongLength,
+    /// The checkpoint interval was zero.
+    BadInterval,
+    /// The given checkpoint had an out-of-order byte offset or an invalid
+    /// state.
+    BadCheckpoint(usize),
+}
+
+impl fmt::Display for IndexFormatError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IndexFormatError::WrongLength => write!(f, "index data has the wrong length"),
+            IndexFormatError::BadInterval => write!(f, "index has a zero checkpoint interval"),
+            IndexFormatError::BadCheckpoint(i) => write!(f, "index checkpoint {} is invalid", i),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IndexFormatError {}
+
+// The serialized form is a header of three little-endian u64s (interval, char
+// length and byte length), then for each checkpoint a u64 byte offset and a
+// u32 state.
+const HEADER_LEN: usize = 24;
+const CHECKPOINT_LEN: usize = 12;
+
+impl Bocu1Index {
+    /// Build an index of `buf` with a checkpoint every `interval` chars (an
+    /// interval of 0 is treated as 1), failing if `buf` is not entirely
+    /// well-formed.
+    pub fn new(buf: &[u8], interval: usize) -> Result<Self, DecodeError> {
+        let interval = interval.max(1);
+        let mut checkpoints = Vec::new();
+        let mut char_len = 0;
+        let mut iter = DecodeResultIter::new(buf);
+        loop {
+            // Note the position before each char, then decode it.
+            let byte_offset = iter.offset();
+            let state = iter.state();
+            match iter.next() {
+                None => break,
+                Some(r) => {
+                    r?;
+                }
+            }
+            if char_len % interval == 0 {
+                checkpoints.push(Checkpoint {
+                    byte_offset: byte_offset,
+                    state: state,
+                });
+            }
+            char_len += 1;
+        }
+        if char_len % interval == 0 {
+            // A final checkpoint at the end, so that seek never runs off it.
+            checkpoints.push(Checkpoint {
+                byte_offset: buf.len(),
+                state: iter.state(),
+            });
+        }
+        Ok(Self {
+            interval: interval,
+            char_len: char_len,
+            byte_len: buf.len(),
+            checkpoints: checkpoints,
+        })
+    }
+
+    /// The number of chars in the indexed text.
+    pub fn char_len(self: &Self) -> usize {
+        self.char_len
+    }
+
+    pub fn interval(self: &Self) -> usize {
+        self.interval
+    }
+
+    /// The char at char offset `n` of `buf`.
+    pub fn char_at(self: &Self, buf: &[u8], n: usize) -> Option<char> {
+        if n >= self.char_len {
+            return None;
+        }
+        let (pos, state) = self.seek(buf, n)?;
+        DecodeResultIter::with_state(&buf[pos..], state)
+            .next()?
+            .ok()
+    }
+
+    /// The byte offset in `buf` at which the code unit of char `n` begins, or
+    /// for `n` equal to char_len, the offset of the end of the text. (Any
+    /// reset bytes between two chars count as part of the later one.)
+    pub fn byte_offset_of(self: &Self, buf: &[u8], n: usize) -> Option<usize> {
+        if n == self.char_len && buf.len() == self.byte_len {
+            return Some(buf.len());
+        }
+        self.seek(buf, n).map(|(pos, _)| pos)
+    }
+
+    /// The chars at char offsets `r` of `buf`.
+    pub fn slice_chars(self: &Self, buf: &[u8], r: Range<usize>) -> Option<String> {
+        if r.start > r.end || r.end > self.char_len {
+            return None;
+        }
+        let (pos, state) = self.seek(buf, r.start)?;
+        DecodeResultIter::with_state(&buf[pos..], state)
+            .take(r.end - r.start)
+            .collect::<Result<String, _>>()
+            .ok()
+    }
+
+    // The byte offset of char n, and the decoder state there, found by
+    // decoding forward from the checkpoint before it.
+    fn seek(self: &Self, buf: &[u8], n: usize) -> Option<(usize, DeltaCoder)> {
+        if n > self.char_len || buf.len() != self.byte_len {
+            return None;
+        }
+        let cp = self.checkpoints[n / self.interval];
+        let mut iter = DecodeResultIter::with_state(&buf[cp.byte_offset..], cp.state);
+        for _ in 0..n % self.interval {
+            iter.next()?.ok()?;
+        }
+        Some((cp.byte_offset + iter.offset(), iter.state()))
+    }
+
+    /// Serialize the index, in a form from_bytes can read back.
+    pub fn to_bytes(self: &Self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(HEADER_LEN + CHECKPOINT_LEN * self.checkpoints.len());
+        for &n in &[self.interval, self.char_len, self.byte_len] {
+            v.extend_from_slice(&(n as u64).to_le_bytes());
+        }
+        for cp in &self.checkpoints {
+            v.extend_from_slice(&(cp.byte_offset as u64).to_le_bytes());
+            v.extend_from_slice(&(cp.state.state() as u32).to_le_bytes());
+        }
+        v
+    }
+
+    /// Read back an index serialized by to_bytes. This checks the index is
+    /// self-consistent, but of course not that it matches any particular
+    /// text.
+    pub fn from_bytes(b: &[u8]) -> Result<Self, IndexFormatError> {
+        let read_u64 = |at: usize| -> Result<usize, IndexFormatError> {
+            let bytes = b.get(at..at + 8).ok_or(IndexFormatError::WrongLength)?;
+            u64::from_le_bytes(bytes.try_into().expect("8 bytes"))
+                .try_into()
+                .map_err(|_| IndexFormatError::WrongLength)
+        };
+        let interval = read_u64(0)?;
+        let char_len = read_u64(8)?;
+        let byte_len = read_u64(16)?;
+        if interval == 0 {
+            return Err(IndexFormatError::BadInterval);
+        }
+        let count = (char_len / interval)
+            .checked_add(1)
+            .ok_or(IndexFormatError::WrongLength)?;
+        if (b.len() - HEADER_LEN) / CHECKPOINT_LEN != count
+            || (b.len() - HEADER_LEN) % CHECKPOINT_LEN != 0
+        {
+            return Err(IndexFormatError::WrongLength);
+        }
+        let mut checkpoints = Vec::with_capacity(count);
+        let mut last = 0;
+        for (i, rec) in b[HEADER_LEN..].chunks(CHECKPOINT_LEN).enumerate() {
+            let byte_offset = read_u64(HEADER_LEN + i * CHECKPOINT_LEN)?;
+            let prev = u32::from_le_bytes(rec[8..].try_into().expect("4 bytes"));
+            let state = core::char::from_u32(prev)
+                .and_then(DeltaCoder::from_state)
+                .ok_or(IndexFormatError::BadCheckpoint(i))?;
+            // The first checkpoint is the start of the text; every other one
+            // is at least a char further on than the one before.
+            let misplaced = if i == 0 {
+                byte_offset != 0 || state != DeltaCoder::new()
+            } else {
+                byte_offset <= last
+            };
+            if misplaced || byte_offset > byte_len {
+                return Err(IndexFormatError::BadCheckpoint(i));
+            }
+            last = byte_offset;
+            checkpoints.push(Checkpoint {
+                byte_offset: byte_offset,
+                state: state,
+            });
+        }
+        Ok(Self {
+            interval: interval,
+            char_len: char_len,
+            byte_len: byte_len,
+            checkpoints: checkpoints,
+        })
+    }
+}
@@ -0,0 +1,392 @@
+//! Adaptors between BOCU-1 byte streams and std::io.
+//!
+//! Like the iter module, none of this has anything to do with BOCU-1 as such:
+//! it just shuffles bytes between readers or writers and the DeltaCoder,
+//! taking care that the coder state survives from one call to the next, and
+//! that a code unit (or, when encoding, a UTF-8 sequence) split across two
+//! calls is put back together before it is decoded.
+
+use crate::delta_encoding::DeltaCoder;
+use crate::options::{EncoderOptions, PolicyCoder};
+use crate::sync::{self, RESET_RUN_LEN};
+use crate::{DecodeError, DecodeErrorKind};
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::str;
+
+// How much encoded input to ask the inner reader for at a time.
+const READ_CHUNK: usize = 8 * 1024;
+
+// How much encoded output to accumulate before passing it to the inner writer.
+const WRITE_CHUNK: usize = 8 * 1024;
+
+/// Wraps a reader of BOCU-1 bytes, and is itself a reader (and BufRead) of
+/// the equivalent UTF-8 bytes.
+///
+/// Malformed input is reported as an io::Error of kind InvalidData, wrapping
+/// a DecodeError whose offset counts from the start of the stream. The error
+/// comes after the text decoded before it, and reading on after it carries on
+/// from the next sync point, as DecodeResultIter does.
+pub struct Bocu1Reader<R> {
+    inner: R,
+    state: DeltaCoder,
+    // Encoded bytes read from inner but not yet decoded. Between refills this
+    // holds at most an incomplete code unit.
+    pending: Vec<u8>,
+    // Stream offset of the first byte in pending.
+    offset: usize,
+    // Decoded UTF-8, of which out[pos..] has not yet been consumed.
+    out: Vec<u8>,
+    pos: usize,
+    // An error found while decoding, to report once out has been consumed.
+    error: Option<DecodeError>,
+    // After an error, the offset in pending from which to look for the next
+    // sync point (the offsets before it having been looked at already).
+    resync_from: Option<usize>,
+}
+
+impl<R> Bocu1Reader<R>
+where
+    R: Read,
+{
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner: inner,
+            state: DeltaCoder::new(),
+            pending: Vec::new(),
+            offset: 0,
+            out: Vec::new(),
+            pos: 0,
+            error: None,
+            resync_from: None,
+        }
+    }
+
+    /// The coder state after the input decoded so far, which may run ahead
+    /// of what has been read out of this reader.
+    pub fn state(self: &Self) -> DeltaCoder {
+        self.state
+    }
+
+    pub fn get_ref(self: &Self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(self: &mut Self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self: Self) -> R {
+        self.inner
+    }
+
+    // Skip to the next sync point after an error, returning whether it has
+    // been reached.
+    fn resync_pending(self: &mut Self, at_eof: bool) -> bool {
+        let Some(from) = self.resync_from else {
+            return true;
+        };
+        let len = self.pending.len();
+        match sync::next_sync_point(&self.pending, from) {
+            Some(j) => {
+                self.pending.drain(..j);
+                self.offset += j;
+                self.resync_from = None;
+                self.state = DeltaCoder::new();
+                true
+            }
+            None if at_eof => {
+                self.pending.clear();
+                self.offset += len;
+                self.resync_from = None;
+                true
+            }
+            None => {
+                // Keep enough of what has been looked at to tell whether the
+                // bytes still to come are sync points.
+                let cut = len.saturating_sub(RESET_RUN_LEN);
+                self.pending.drain(..cut);
+                self.offset += cut;
+                self.resync_from = Some(len.max(from) - cut);
+                false
+            }
+        }
+    }
+
+    // Decode as many complete code units as are pending. At end of stream an
+    // incomplete code unit is an error; otherwise it waits for more input.
+    // Decoding stops at an error, which is kept to be reported after the
+    // output before it.
+    fn decode_pending(self: &mut Self, at_eof: bool) {
+        if !self.resync_pending(at_eof) {
+            return;
+        }
+        let mut i = 0;
+        while i < self.pending.len() {
+            match self.state.decode_char(&self.pending[i..]) {
+                Ok((c, rest)) => {
+                    if let Some(c) = c {
+                        let mut utf8 = [0_u8; 4];
+                        self.out
+                            .extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                    }
+                    i = self.pending.len() - rest.len();
+                }
+                Err(e) if e.kind() == DecodeErrorKind::TruncatedInput && !at_eof => break,
+                Err(e) => {
+                    self.error = Some(e.offset_by(self.offset + i));
+                    self.resync_from = Some(1);
+                    break;
+                }
+            }
+        }
+        self.pending.drain(..i);
+        self.offset += i;
+    }
+
+    // Read and decode until there is some output, an error, or the stream is
+    // done.
+    fn refill(self: &mut Self) -> io::Result<()> {
+        self.out.clear();
+        self.pos = 0;
+        // Decoding stopped at the last error with input still pending, which
+        // has to be decoded before reading any more: the inner reader may have
+        // nothing more to give until it has been.
+        let mut undecoded = self.resync_from.is_some() && !self.pending.is_empty();
+        loop {
+            if !self.out.is_empty() {
+                return Ok(());
+            }
+            if let Some(e) = self.error.take() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+            if undecoded {
+                undecoded = false;
+                self.decode_pending(false);
+                continue;
+            }
+            let have = self.pending.len();
+            self.pending.resize(have + READ_CHUNK, 0);
+            let res = self.inner.read(&mut self.pending[have..]);
+            let n = *res.as_ref().unwrap_or(&0);
+            self.pending.truncate(have + n);
+            match res {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+                Ok(0) => {
+                    self.decode_pending(true);
+                    if self.out.is_empty() && self.error.is_none() {
+                        return Ok(());
+                    }
+                }
+                Ok(_) => self.decode_pending(false),
+            }
+        }
+    }
+}
+
+impl<R> Read for Bocu1Reader<R>
+where
+    R: Read,
+{
+    fn read(self: &mut Self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let avail = self.fill_buf()?;
+            let n = avail.len().min(buf.len());
+            buf[..n].copy_from_slice(&avail[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R> BufRead for Bocu1Reader<R>
+where
+    R: Read,
+{
+    fn fill_buf(self: &mut Self) -> io::Result<&[u8]> {
+        if self.pos == self.out.len() {
+            self.refill()?;
+        }
+        Ok(&self.out[self.pos..])
+    }
+
+    fn consume(self: &mut Self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.out.len());
+    }
+}
+
+/// Wraps a writer of BOCU-1 bytes, and is itself a writer of UTF-8 bytes,
+/// encoding them as they arrive.
+///
+/// Input may be split anywhere, including inside a multibyte UTF-8 sequence;
+/// the incomplete tail of one write is held over to the next. Encoded output
+/// is buffered, and only ever passed on with write_all, so a short write from
+/// the inner writer never drops bytes. As with io::BufWriter, output is
+/// flushed on drop but any error in doing so is ignored: call flush or finish
+/// to see it.
+pub struct Bocu1Writer<W>
+where
+    W: Write,
+{
+    inner: Option<W>,
+    state: PolicyCoder,
+    // An incomplete UTF-8 sequence left over from the previous write.
+    partial: [u8; 4],
+    partial_len: usize,
+    // Encoded bytes not yet passed to inner.
+    out: Vec<u8>,
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "input is not valid UTF-8")
+}
+
+fn incomplete_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "input ends with an incomplete UTF-8 sequence",
+    )
+}
+
+impl<W> Bocu1Writer<W>
+where
+    W: Write,
+{
+    pub fn new(inner: W) -> Self {
+        Self::with_options(inner, EncoderOptions::default())
+    }
+
+    /// As new, but encoding according to `options`.
+    pub fn with_options(inner: W, options: EncoderOptions) -> Self {
+        Self::with_options_and_state(inner, options, DeltaCoder::new())
+    }
+
+    /// As new, but carrying on from `state`: for appending to a stream whose
+    /// final coder state was saved when it was written.
+    pub fn with_state(inner: W, state: DeltaCoder) -> Self {
+        Self::with_options_and_state(inner, EncoderOptions::default(), state)
+    }
+
+    pub fn with_options_and_state(inner: W, options: EncoderOptions, state: DeltaCoder) -> Self {
+        Self {
+            inner: Some(inner),
+            state: PolicyCoder::with_state(options, state),
+            partial: [0; 4],
+            partial_len: 0,
+            out: Vec::new(),
+        }
+    }
+
+    /// The coder state after everything written so far (not counting any
+    /// incomplete UTF-8 sequence held over).
+    pub fn state(self: &Self) -> DeltaCoder {
+        self.state.state()
+    }
+
+    pub fn get_ref(self: &Self) -> &W {
+        self.inner.as_ref().expect("Bocu1Writer used after finish")
+    }
+
+    pub fn get_mut(self: &mut Self) -> &mut W {
+        self.inner.as_mut().expect("Bocu1Writer used after finish")
+    }
+
+    /// Write out everything encoded so far and return the inner writer. It is
+    /// an error for the input to end partway through a UTF-8 sequence.
+    pub fn finish(mut self: Self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.inner.take().expect("Bocu1Writer used after finish"))
+    }
+
+    fn encode_str(self: &mut Self, s: &str) {
+        for c in s.chars() {
+            let (marker, enc) = self.state.encode_char(c);
+            if let Some(marker) = marker {
+                self.out.extend_from_slice(marker.as_slice());
+            }
+            self.out.extend_from_slice(enc.as_slice());
+        }
+    }
+
+    fn flush_out(self: &mut Self) -> io::Result<()> {
+        if !self.out.is_empty() {
+            let inner = self.inner.as_mut().expect("Bocu1Writer used after finish");
+            inner.write_all(&self.out)?;
+            self.out.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<W> Write for Bocu1Writer<W>
+where
+    W: Write,
+{
+    fn write(self: &mut Self, buf: &[u8]) -> io::Result<usize> {
+        // Pass on earlier output before accepting more input, so that an
+        // error here leaves buf unconsumed.
+        if self.out.len() >= WRITE_CHUNK {
+            self.flush_out()?;
+        }
+
+        // First try to complete a sequence left over from the last write.
+        let mut consumed = 0;
+        while self.partial_len != 0 && consumed < buf.len() {
+            self.partial[self.partial_len] = buf[consumed];
+            self.partial_len += 1;
+            consumed += 1;
+            let seq = self.partial;
+            match str::from_utf8(&seq[..self.partial_len]) {
+                Ok(s) => {
+                    self.partial_len = 0;
+                    self.encode_str(s);
+                }
+                Err(e) if e.error_len().is_none() => (),
+                Err(_) => {
+                    self.partial_len = 0;
+                    return Err(invalid_utf8());
+                }
+            }
+        }
+
+        let rest = &buf[consumed..];
+        match str::from_utf8(rest) {
+            Ok(s) => self.encode_str(s),
+            Err(e) => {
+                let (valid, tail) = rest.split_at(e.valid_up_to());
+                self.encode_str(str::from_utf8(valid).expect("checked by from_utf8"));
+                if e.error_len().is_some() {
+                    // Accept the valid prefix, and report the bad sequence
+                    // when the caller retries from it.
+                    let n = consumed + valid.len();
+                    return if n == 0 { Err(invalid_utf8()) } else { Ok(n) };
+                }
+                self.partial[..tail.len()].copy_from_slice(tail);
+                self.partial_len = tail.len();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(self: &mut Self) -> io::Result<()> {
+        self.flush_out()?;
+        self.get_mut().flush()?;
+        if self.partial_len != 0 {
+            return Err(incomplete_utf8());
+        }
+        Ok(())
+    }
+}
+
+impl<W> Drop for Bocu1Writer<W>
+where
+    W: Write,
+{
+    fn drop(self: &mut Self) {
+        if self.inner.is_some() {
+            let _ = self.flush_out();
+        }
+    }
+}
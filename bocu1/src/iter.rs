@@ -6,9 +6,17 @@
 * {en,de}coder routines in the other modules.
 */
 
-#![allow(clippy::stutter)]
+#![allow(clippy::module_name_repetitions)]
 
 use crate::delta_encoding;
+use crate::options::{EncoderOptions, PolicyCoder};
+use crate::sync;
+#[cfg(feature = "alloc")]
+use alloc::collections::VecDeque;
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
 use std::io;
 
 // There are two levels of encoding iterator: one that returns chunks of
@@ -37,7 +45,9 @@ where
     IT: Iterator<Item = char>,
 {
     input: IT,
-    coder: delta_encoding::DeltaCoder,
+    coder: PolicyCoder,
+    // A char's chunk, held back while the reset marker before it is returned.
+    pending: Option<EncodedChunk>,
 }
 
 impl<IT> EncodedChunkIter<IT>
@@ -45,11 +55,34 @@ where
     IT: Iterator<Item = char>,
 {
     pub fn new(input: IT) -> Self {
+        Self::with_options(input, EncoderOptions::default())
+    }
+
+    /// As new, but encoding according to `options`. Any reset marker is
+    /// returned as a chunk of its own, before the chunk of the char it
+    /// precedes.
+    pub fn with_options(input: IT, options: EncoderOptions) -> Self {
+        Self {
+            input: input,
+            coder: PolicyCoder::new(options),
+            pending: None,
+        }
+    }
+
+    /// As new, but carrying on from `state` (say, the state at the end of
+    /// some existing encoded text, to append to it).
+    pub fn with_state(input: IT, state: delta_encoding::DeltaCoder) -> Self {
         Self {
             input: input,
-            coder: delta_encoding::DeltaCoder::new(),
+            coder: PolicyCoder::with_state(EncoderOptions::default(), state),
+            pending: None,
         }
     }
+
+    /// The coder state after the chars encoded so far.
+    pub fn state(self: &Self) -> delta_encoding::DeltaCoder {
+        self.coder.state()
+    }
 }
 
 impl<IT> Iterator for EncodedChunkIter<IT>
@@ -58,9 +91,16 @@ where
 {
     type Item = EncodedChunk;
     fn next(self: &mut Self) -> Option<EncodedChunk> {
-        match self.input.next() {
-            None => None,
-            Some(ch) => Some(self.coder.encode_char(ch)),
+        if let Some(enc) = self.pending.take() {
+            return Some(enc);
+        }
+        let ch = self.input.next()?;
+        match self.coder.encode_char(ch) {
+            (None, enc) => Some(enc),
+            (Some(marker), enc) => {
+                self.pending = Some(enc);
+                Some(marker)
+            }
         }
     }
 }
@@ -87,6 +127,17 @@ where
     }
 }
 
+impl<IT> DrainEncodedChunkIter<EncodedChunkIter<IT>>
+where
+    IT: Iterator<Item = char>,
+{
+    /// The coder state after the chars encoded so far, once their bytes
+    /// have all been drained.
+    pub fn state(self: &Self) -> delta_encoding::DeltaCoder {
+        self.inner.state()
+    }
+}
+
 impl<IT> Iterator for DrainEncodedChunkIter<IT>
 where
     IT: Iterator<Item = EncodedChunk>,
@@ -128,37 +179,66 @@ pub trait EncodeBOCU1 {
 }
 
 impl<'a> EncodeBOCU1 for &'a str {
-    type IT = ::std::str::Chars<'a>;
+    type IT = ::core::str::Chars<'a>;
     fn encode_bocu1(self: &Self) -> EncodeIter<Self::IT> {
         let inner = EncodedChunkIter::new(self.chars());
         DrainEncodedChunkIter::new(inner)
     }
 }
 impl<'a> EncodeBOCU1 for &'a [char] {
-    type IT = ::std::iter::Cloned<::std::slice::Iter<'a, char>>;
+    type IT = ::core::iter::Cloned<::core::slice::Iter<'a, char>>;
     fn encode_bocu1(self: &Self) -> EncodeIter<Self::IT> {
         let inner = EncodedChunkIter::new(self.iter().cloned());
         DrainEncodedChunkIter::new(inner)
     }
 }
 
-impl<'a> EncodeBOCU1 for ::std::slice::Iter<'a, char> {
-    type IT = ::std::iter::Cloned<Self>;
+impl<'a> EncodeBOCU1 for ::core::slice::Iter<'a, char> {
+    type IT = ::core::iter::Cloned<Self>;
     fn encode_bocu1(self: &Self) -> EncodeIter<Self::IT> {
         let inner = EncodedChunkIter::new(self.clone().cloned());
         DrainEncodedChunkIter::new(inner)
     }
 }
 
+/// The exact number of bytes that encoding `s` will produce.
+pub fn encoded_len(s: &str) -> usize {
+    let mut e = delta_encoding::DeltaCoder::new();
+    s.chars().map(|c| e.encoded_char_len(c)).sum()
+}
+
+/// An upper bound on the number of bytes that encoding any `char_count`
+/// chars can produce: every char's code unit is at most 4 bytes. Returns
+/// None if that bound overflows a usize.
+pub fn max_encoded_len(char_count: usize) -> Option<usize> {
+    char_count.checked_mul(4)
+}
+
+/// Encode a whole string to a writer, returning the number of bytes written.
+/// Each call starts from the initial encoder state; to encode a stream of
+/// pieces, use io::Bocu1Writer instead.
+#[cfg(feature = "std")]
 pub fn write_encoded_chars<W>(s: &str, out: &mut W) -> io::Result<usize>
+where
+    W: io::Write,
+{
+    write_encoded_chars_with_options(s, out, EncoderOptions::default())
+}
+
+/// As write_encoded_chars, but encoding according to `options`.
+#[cfg(feature = "std")]
+pub fn write_encoded_chars_with_options<W>(
+    s: &str,
+    out: &mut W,
+    options: EncoderOptions,
+) -> io::Result<usize>
 where
     W: io::Write,
 {
     let mut total = 0;
-    let mut e = delta_encoding::DeltaCoder::new();
-    for c in s.chars() {
-        let enc = e.encode_char(c);
-        total += out.write(enc.as_slice())?;
+    for enc in EncodedChunkIter::with_options(s.chars(), options) {
+        out.write_all(enc.as_slice())?;
+        total += enc.count;
     }
     Ok(total)
 }
@@ -166,14 +246,104 @@ where
 // The most straightforward way to decode is just to call .decode_bocu1()
 // on the encoded bytes and collect the resulting characters. It will only
 // return the error-free prefix though; if you want a more-detailed view
-// that accounts for errors, you need to use DecodeResultIter.
+// that accounts for errors, you need to use DecodeResultIter, or
+// .decode_bocu1_lossy() which substitutes U+FFFD for each malformed code
+// unit, in the manner of String::from_utf8_lossy.
 
-pub enum DecodeError {
+/// The specific way in which a code unit failed to decode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The input ended partway through a multibyte code unit.
     TruncatedInput,
+    /// A trailing byte was one of the byte values that trail_to_byte never
+    /// emits.
     TrailByteOutOfRange(u8),
-    CharDeltaOutOfRange(char, i32),
+    /// The code unit held a well-formed delta, but adding it to the
+    /// previous-value state did not land on a Unicode scalar value.
+    CharDeltaOutOfRange(i32),
+    /// A packed scalar held a byte its layout doesn't allow there: a zero
+    /// byte inside a string in the plain layout, or something other than
+    /// 0x01 or 0x02 after an escape byte in the escaped layout (see the
+    /// packed module).
+    BadPackedByte(u8),
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeErrorKind::TruncatedInput => write!(f, "truncated code unit"),
+            DecodeErrorKind::TrailByteOutOfRange(b) => {
+                write!(f, "trailing byte 0x{:02x} out of range", b)
+            }
+            DecodeErrorKind::CharDeltaOutOfRange(d) => {
+                write!(f, "delta {} does not reach a unicode scalar value", d)
+            }
+            DecodeErrorKind::BadPackedByte(b) => {
+                write!(f, "byte 0x{:02x} not allowed in packed string", b)
+            }
+        }
+    }
+}
+
+/// A decoding failure, positioned at the code unit that caused it.
+///
+/// The offset is a byte offset from the start of the input being decoded:
+/// for the iterators that is the whole input slice (or packed string), while
+/// DeltaCoder::decode_char reports offsets relative to the slice it was
+/// handed, which always begins at the offending code unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    kind: DecodeErrorKind,
+    offset: usize,
+    prev: char,
+}
+
+impl DecodeError {
+    pub(crate) fn new(kind: DecodeErrorKind, offset: usize, prev: char) -> Self {
+        Self {
+            kind: kind,
+            offset: offset,
+            prev: prev,
+        }
+    }
+
+    /// Shift the error's offset by the number of input bytes that preceded
+    /// the slice it was reported against.
+    pub(crate) fn offset_by(self: Self, n: usize) -> Self {
+        Self {
+            offset: self.offset + n,
+            ..self
+        }
+    }
+
+    pub fn kind(self: &Self) -> DecodeErrorKind {
+        self.kind
+    }
+
+    /// Byte offset of the first byte of the offending code unit.
+    pub fn offset(self: &Self) -> usize {
+        self.offset
+    }
+
+    /// The decoder's (normalized) previous-value state when the error occurred.
+    pub fn prev(self: &Self) -> char {
+        self.prev
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid BOCU-1 at byte offset {}: {} (previous-value state U+{:04X})",
+            self.offset, self.kind, self.prev as u32
+        )
+    }
 }
 
+#[cfg(feature = "std")]
+impl ::std::error::Error for DecodeError {}
+
 pub struct DecodeIter<'a> {
     inner: DecodeResultIter<'a>,
 }
@@ -196,19 +366,193 @@ impl<'a> Iterator for DecodeIter<'a> {
     }
 }
 
+pub struct DecodeLossyIter<'a> {
+    inner: DecodeResultIter<'a>,
+    replacements: usize,
+}
+
+impl<'a> DecodeLossyIter<'a> {
+    pub fn new(s: &'a [u8]) -> DecodeLossyIter<'a> {
+        DecodeLossyIter {
+            inner: DecodeResultIter::new(s),
+            replacements: 0,
+        }
+    }
+
+    /// The number of U+FFFD replacement characters emitted so far.
+    pub fn replacements(self: &Self) -> usize {
+        self.replacements
+    }
+}
+
+impl<'a> Iterator for DecodeLossyIter<'a> {
+    type Item = char;
+    fn next(self: &mut Self) -> Option<char> {
+        match self.inner.next() {
+            None => None,
+            Some(Ok(c)) => Some(c),
+            Some(Err(_)) => {
+                self.replacements += 1;
+                Some(::core::char::REPLACEMENT_CHARACTER)
+            }
+        }
+    }
+}
+
+/// Decode a whole buffer lossily, returning the decoded text along with the
+/// number of replacement characters it contains that were not present in the
+/// input.
+#[cfg(feature = "alloc")]
+pub fn decode_lossy(s: &[u8]) -> (String, usize) {
+    let mut iter = DecodeLossyIter::new(s);
+    let decoded: String = iter.by_ref().collect();
+    (decoded, iter.replacements())
+}
+
+// DoubleEndedDecodeIter decodes from both ends of a buffer. Decoding has to
+// run forwards, so from the back end it works a segment at a time: it scans
+// back to the nearest sync point before the part not yet decoded, decodes the
+// segment from there forwards, and hands out its chars in reverse. Only one
+// segment is held at a time, so memory use is bounded by the distance between
+// sync points (see EncoderOptions, to bound that in turn).
+
+/// A lossy decoding iterator, like DecodeLossyIter, that can also be run
+/// backwards (with .rev() or .next_back()) without decoding the whole buffer.
+#[cfg(feature = "alloc")]
+pub struct DoubleEndedDecodeIter<'a> {
+    buf: &'a [u8],
+    // Bytes front..back have not been decoded yet; the state is that of the
+    // decoder at front.
+    front: usize,
+    back: usize,
+    state: delta_encoding::DeltaCoder,
+    // The chars, not yet returned from either end, of the segment that
+    // begins at back.
+    segment: VecDeque<char>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> DoubleEndedDecodeIter<'a> {
+    pub fn new(s: &'a [u8]) -> DoubleEndedDecodeIter<'a> {
+        DoubleEndedDecodeIter {
+            buf: s,
+            front: 0,
+            back: s.len(),
+            state: delta_encoding::DeltaCoder::new(),
+            segment: VecDeque::new(),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Iterator for DoubleEndedDecodeIter<'a> {
+    type Item = char;
+    fn next(self: &mut Self) -> Option<char> {
+        if self.front < self.back {
+            let mut iter =
+                DecodeResultIter::with_state(&self.buf[self.front..self.back], self.state);
+            let r = iter.next();
+            self.front += iter.offset();
+            self.state = iter.state();
+            // A None here means only reset bytes were left before back.
+            if let Some(r) = r {
+                return Some(r.unwrap_or(::core::char::REPLACEMENT_CHARACTER));
+            }
+        }
+        self.segment.pop_front()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> DoubleEndedIterator for DoubleEndedDecodeIter<'a> {
+    fn next_back(self: &mut Self) -> Option<char> {
+        while self.segment.is_empty() && self.front < self.back {
+            // Decode back to the nearest sync point, or to front if that is
+            // nearer, since the state there is known too.
+            let start = sync::prev_sync_point(self.buf, self.back - 1).unwrap_or(0);
+            let (start, state) = if start > self.front {
+                (start, delta_encoding::DeltaCoder::new())
+            } else {
+                (self.front, self.state)
+            };
+            let seg = &self.buf[start..self.back];
+            self.segment.extend(
+                DecodeResultIter::with_state(seg, state)
+                    .map(|r| r.unwrap_or(::core::char::REPLACEMENT_CHARACTER)),
+            );
+            self.back = start;
+        }
+        self.segment.pop_back()
+    }
+}
+
+/// Start decoding `buf` partway through, at the first sync point at or after
+/// byte `offset` (see the sync module), returning that point's position along
+/// with an iterator over the chars from there. Error offsets from the
+/// iterator count from the start of `buf`. Returns None if there is no sync
+/// point at or after `offset`.
+pub fn decode_from(buf: &[u8], offset: usize) -> Option<(usize, DecodeResultIter<'_>)> {
+    let start = sync::next_sync_point(buf, offset)?;
+    Some((start, DecodeResultIter::starting_at(buf, start)))
+}
+
+/// Check that an entire buffer is well-formed BOCU-1, returning the number of
+/// characters it decodes to, or the first error found.
+pub fn validate(s: &[u8]) -> Result<usize, DecodeError> {
+    validate_with_state(s).map(|(count, _)| count)
+}
+
+/// As validate, but also returning the decoder state at the end of the input,
+/// for callers that want to carry on encoding after it.
+pub(crate) fn validate_with_state(
+    s: &[u8],
+) -> Result<(usize, delta_encoding::DeltaCoder), DecodeError> {
+    let mut count = 0;
+    let mut iter = DecodeResultIter::new(s);
+    for r in iter.by_ref() {
+        r?;
+        count += 1;
+    }
+    Ok((count, iter.state()))
+}
+
+/// Decode an entire buffer to a String, failing on the first malformed code
+/// unit (including a truncated one at the end) rather than silently returning
+/// the prefix before it as .decode_bocu1() does.
+#[cfg(feature = "alloc")]
+pub fn from_bocu1(s: &[u8]) -> Result<String, DecodeError> {
+    DecodeResultIter::new(s).collect()
+}
+
 pub trait DecodeBOCU1 {
     fn decode_bocu1(self: &Self) -> DecodeIter;
+    fn decode_bocu1_lossy(self: &Self) -> DecodeLossyIter;
 }
 
 impl<'a> DecodeBOCU1 for &'a [u8] {
     fn decode_bocu1(self: &Self) -> DecodeIter {
         DecodeIter::new(self)
     }
+    fn decode_bocu1_lossy(self: &Self) -> DecodeLossyIter {
+        DecodeLossyIter::new(self)
+    }
+}
+
+/// The number of bytes to skip, after a malformed code unit at the start of
+/// `b`, to reach the next point where decoding can safely resume: the next
+/// sync point, or the end of the input if there is none.
+pub(crate) fn resync_len(b: &[u8]) -> usize {
+    sync::next_sync_point(b, 1).unwrap_or(b.len())
 }
 
+// DecodeResultIter yields an Err for each malformed code unit it finds, then
+// skips forward to the next sync point (see the sync module) and
+// carries on decoding from a freshly reset state.
+
 pub struct DecodeResultIter<'a> {
     state: delta_encoding::DeltaCoder,
     slice: &'a [u8],
+    offset: usize,
 }
 
 impl<'a> DecodeResultIter<'a> {
@@ -216,8 +560,39 @@ impl<'a> DecodeResultIter<'a> {
         DecodeResultIter {
             state: delta_encoding::DeltaCoder::new(),
             slice: s,
+            offset: 0,
         }
     }
+
+    // As new, but decoding buf[start..] and reporting error offsets relative
+    // to the start of buf.
+    fn starting_at(buf: &'a [u8], start: usize) -> DecodeResultIter<'a> {
+        DecodeResultIter {
+            state: delta_encoding::DeltaCoder::new(),
+            slice: &buf[start..],
+            offset: start,
+        }
+    }
+
+    /// As new, but resuming from a known decoder state rather than the
+    /// initial one.
+    pub fn with_state(s: &'a [u8], state: delta_encoding::DeltaCoder) -> DecodeResultIter<'a> {
+        DecodeResultIter {
+            state: state,
+            slice: s,
+            offset: 0,
+        }
+    }
+
+    /// The number of bytes consumed so far.
+    pub fn offset(self: &Self) -> usize {
+        self.offset
+    }
+
+    /// The decoder state after the bytes consumed so far.
+    pub fn state(self: &Self) -> delta_encoding::DeltaCoder {
+        self.state
+    }
 }
 
 impl<'a> Iterator for DecodeResultIter<'a> {
@@ -228,12 +603,21 @@ impl<'a> Iterator for DecodeResultIter<'a> {
                 return None;
             }
             match self.state.decode_char(self.slice) {
-                Ok((None, rest)) => self.slice = rest,
+                Ok((None, rest)) => {
+                    self.offset += self.slice.len() - rest.len();
+                    self.slice = rest;
+                }
                 Ok((Some(c), rest)) => {
+                    self.offset += self.slice.len() - rest.len();
                     self.slice = rest;
                     return Some(Ok(c));
                 }
                 Err(e) => {
+                    let e = e.offset_by(self.offset);
+                    let skip = resync_len(self.slice);
+                    self.offset += skip;
+                    self.slice = &self.slice[skip..];
+                    self.state = delta_encoding::DeltaCoder::new();
                     return Some(Err(e));
                 }
             }
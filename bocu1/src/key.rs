@@ -0,0 +1,187 @@
+//! A key type for ordered collections that holds short strings inline, packed
+//! into a u128, and only puts longer ones on the heap.
+//!
+//! A string is held inline exactly when it can be packed (in the plain
+//! layout, see the packed module): when its encoding is at most 16 bytes and
+//! has no NUL byte in it. Keys made from encoded strings are re-encoded if
+//! need be, dropping any reset bytes (see the sync module), so that they hold
+//! what the encoder would produce. Every string thus has just one
+//! representation, so keys are equal exactly when their strings are, and two
+//! inline keys can be compared with a single scalar compare.
+//!
+//! An inline key is compared with a heap key by packing the first 16 bytes of
+//! the heap key's encoding the same way, zero-padded if there are fewer, and
+//! comparing the scalars. Where they differ, that settles it, since the
+//! padding sorts below any byte of the inline key, and (being zero) at or
+//! below the byte of the heap key it stands in for. Where they are the same,
+//! the inline key's encoding must be a proper prefix of the heap key's (it
+//! can't be all of it, or the heap key would have been inline), so the heap
+//! key is the greater. Either way this is the byte order of the encodings,
+//! and so the codepoint order of the strings.
+
+use crate::packed::{pack_bytes, PackedStr};
+use crate::variable_length_code::LEAD_BYTE_RESET;
+use crate::{Bocu1Str, Bocu1String};
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::fmt;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Inline(PackedStr<u128>),
+    Heap(Bocu1String),
+}
+
+/// A string key, held inline when it is short enough to pack into a u128
+/// and on the heap otherwise, ordered by the codepoint order of its string.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Bocu1Key {
+    repr: Repr,
+}
+
+// Pack the first 16 bytes of `b` into the high end of a u128, zero-padding
+// the rest.
+fn pack_leading_bytes(b: &[u8]) -> u128 {
+    let mut buf = [0_u8; 16];
+    let n = b.len().min(16);
+    buf[..n].copy_from_slice(&b[..n]);
+    u128::from_be_bytes(buf)
+}
+
+// Pack an encoding, if it can be held inline.
+fn pack_exact(b: &[u8]) -> Option<PackedStr<u128>> {
+    pack_bytes(b).ok().map(PackedStr::from_scalar)
+}
+
+impl Bocu1Key {
+    pub fn new(s: &str) -> Self {
+        match PackedStr::try_from(s) {
+            Ok(p) => Self {
+                repr: Repr::Inline(p),
+            },
+            Err(_) => Self {
+                repr: Repr::Heap(Bocu1String::from(s)),
+            },
+        }
+    }
+
+    /// Whether the key is held inline, rather than on the heap.
+    pub fn is_inline(self: &Self) -> bool {
+        matches!(self.repr, Repr::Inline(_))
+    }
+
+    /// The packed form of the key, if it is held inline.
+    pub fn as_packed(self: &Self) -> Option<PackedStr<u128>> {
+        match self.repr {
+            Repr::Inline(p) => Some(p),
+            Repr::Heap(_) => None,
+        }
+    }
+
+    /// The length of the key's encoding, in bytes.
+    pub fn len(self: &Self) -> usize {
+        match self.repr {
+            Repr::Inline(p) => p.len(),
+            Repr::Heap(ref s) => s.len(),
+        }
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_bocu1_string(self: &Self) -> Bocu1String {
+        match self.repr {
+            Repr::Inline(p) => Bocu1String::from_bytes(p.as_bytes().to_vec())
+                .expect("packed strings hold valid BOCU-1"),
+            Repr::Heap(ref s) => s.clone(),
+        }
+    }
+}
+
+impl PartialOrd for Bocu1Key {
+    fn partial_cmp(self: &Self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Bocu1Key {
+    fn cmp(self: &Self, other: &Self) -> Ordering {
+        match (&self.repr, &other.repr) {
+            (Repr::Inline(a), Repr::Inline(b)) => a.cmp(b),
+            (Repr::Heap(a), Repr::Heap(b)) => a.cmp(b),
+            (Repr::Inline(a), Repr::Heap(b)) => a
+                .to_scalar()
+                .cmp(&pack_leading_bytes(b.as_bytes()))
+                .then(Ordering::Less),
+            (Repr::Heap(a), Repr::Inline(b)) => pack_leading_bytes(a.as_bytes())
+                .cmp(&b.to_scalar())
+                .then(Ordering::Greater),
+        }
+    }
+}
+
+impl From<&str> for Bocu1Key {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+// Whether an encoding may not be the one the encoder would produce for its
+// text, because it may have reset bytes in it. (It may also just have 0xFF
+// trailing bytes, but re-encoding those does no harm.)
+fn maybe_non_canonical(b: &[u8]) -> bool {
+    b.contains(&LEAD_BYTE_RESET)
+}
+
+impl From<&Bocu1Str> for Bocu1Key {
+    fn from(s: &Bocu1Str) -> Self {
+        if maybe_non_canonical(s.as_bytes()) {
+            return Self::from(s.chars().collect::<Bocu1String>());
+        }
+        match pack_exact(s.as_bytes()) {
+            Some(p) => Self {
+                repr: Repr::Inline(p),
+            },
+            None => Self {
+                repr: Repr::Heap(Bocu1String::from(s)),
+            },
+        }
+    }
+}
+
+impl From<Bocu1String> for Bocu1Key {
+    fn from(s: Bocu1String) -> Self {
+        let s = if maybe_non_canonical(s.as_bytes()) {
+            s.chars().collect()
+        } else {
+            s
+        };
+        match pack_exact(s.as_bytes()) {
+            Some(p) => Self {
+                repr: Repr::Inline(p),
+            },
+            None => Self {
+                repr: Repr::Heap(s),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Bocu1Key {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.repr {
+            Repr::Inline(ref p) => fmt::Display::fmt(p, f),
+            Repr::Heap(ref s) => fmt::Display::fmt(s, f),
+        }
+    }
+}
+
+impl fmt::Debug for Bocu1Key {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.repr {
+            Repr::Inline(ref p) => fmt::Debug::fmt(p, f),
+            Repr::Heap(ref s) => fmt::Debug::fmt(s, f),
+        }
+    }
+}
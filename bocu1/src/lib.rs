@@ -1,5 +1,23 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::redundant_field_names)] // I happen to like these.
+// I also like spelling out `self: &Self`.
+#![allow(clippy::needless_arbitrary_self_type)]
+// Pedantic lints that arrived after this crate was written and that fight its
+// prose-heavy doc comment style.
+#![allow(clippy::doc_markdown)]
+#![allow(clippy::doc_lazy_continuation)]
+#![allow(clippy::missing_errors_doc)]
+#![allow(clippy::missing_panics_doc)]
+#![allow(clippy::must_use_candidate)]
+#![allow(clippy::uninlined_format_args)]
+// And one that suggests methods newer than the Rust versions it supports.
+#![allow(clippy::manual_is_multiple_of)]
+// And some that would have it rewrite code it already had.
+#![allow(clippy::cloned_instead_of_copied)]
+#![allow(clippy::elidable_lifetime_names)]
+#![allow(clippy::manual_range_contains)]
+#![allow(clippy::needless_lifetimes)]
+#![allow(mismatched_lifetime_syntaxes)]
 
 //!  This crate serves two purposes:
 //!
@@ -116,31 +134,136 @@
 //!  is therefore organized into 3 main sub-modules (plus some helpers), one for
 //!  each such part, exposing only the bits that need to be shared between them.
 //!
+//!
+//!  Cargo features
+//!  ==============
+//!
+//!  The codec itself is pure arithmetic and works in no_std environments. The
+//!  rest is divided up by what it needs from the standard library:
+//!
+//!    - `std` (default): the io module's reader and writer adaptors,
+//!      write_encoded_chars, and std::error::Error for DecodeError. Implies
+//!      `alloc`.
+//!
+//!    - `alloc`: the owned string types, and everything else that allocates:
+//!      the index, splice, search, ordering and key modules,
+//!      DoubleEndedDecodeIter and the functions that return Strings.
+//!
+//!    - `log` (default): trace-level logging of each encoding step through
+//!      the log crate, compiled out of release builds.
+//!
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
 #[macro_use]
 extern crate static_assertions;
+#[cfg(feature = "log")]
 #[macro_use]
 extern crate log;
+extern crate memchr;
 extern crate num_integer;
-extern crate try_from;
+
+// Without the log feature, tracing compiles away to nothing (but still
+// type-checks its arguments).
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+#[cfg(all(test, feature = "std", not(feature = "log")))]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
 
 // These three modules implement BOCU-1 as described above.
 pub mod delta_encoding;
 pub mod trailing_byte_selection;
 pub mod variable_length_code;
 
+// The options module implements encoder options, and the sync module finds
+// the points in an encoded stream where decoding can start afresh, including
+// the reset markers those options can have the encoder inject.
+pub mod options;
+pub use options::{EncoderOptions, ResetPolicy};
+pub mod sync;
+
+// The split module implements splitting encoded text into lines or other
+// records at C0 controls, without decoding it.
+pub mod split;
+
 // The iter module implements a convenience interface with Rust types; this
 // is re-exported as the main intended interface for using the crate.
 pub mod iter;
 pub use iter::*;
 
+// The incremental module implements a push-style decoder that is fed one
+// byte (or one chunk of bytes) at a time, like the C reference decoder.
+pub mod incremental;
+pub use incremental::IncrementalDecoder;
+
+// The buffer module implements encoding_rs-style encoders and decoders that
+// convert between caller-provided fixed-size buffers, resumably.
+pub mod buffer;
+
+// The index module implements a checkpoint index for random access by char
+// offset into long texts.
+#[cfg(feature = "alloc")]
+pub mod index;
+#[cfg(feature = "alloc")]
+pub use index::Bocu1Index;
+
+// The splice module implements joining and cutting encoded strings while
+// re-encoding as little of them as possible.
+#[cfg(feature = "alloc")]
+pub mod splice;
+
+// The search module implements substring search on encoded bytes.
+#[cfg(feature = "alloc")]
+pub mod search;
+
+// The ordering module implements helpers for using encoded strings as keys
+// in ordered stores.
+#[cfg(feature = "alloc")]
+pub mod ordering;
+
+// The string module implements owned and borrowed string types that hold
+// known-valid BOCU-1 bytes, analogous to String and str.
+#[cfg(feature = "alloc")]
+pub mod string;
+#[cfg(feature = "alloc")]
+pub use string::{Bocu1Str, Bocu1String};
+
+// The io module implements adaptors that decode or encode BOCU-1 streams
+// through std::io readers and writers.
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "std")]
+pub use io::{Bocu1Reader, Bocu1Writer};
+
 // The packed module implements a set of interfaces to store small BOCU-1
 // strings in scalars.
 pub mod packed;
+pub use packed::PackedStr;
+
+// The key module implements a key type for ordered collections that packs
+// short strings inline and puts only long ones on the heap.
+#[cfg(feature = "alloc")]
+pub mod key;
+#[cfg(feature = "alloc")]
+pub use key::Bocu1Key;
 
 // Miscellaneous supporting code.
 mod util;
 
 // Test module.
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests;
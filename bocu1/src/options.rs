@@ -0,0 +1,128 @@
+//! Options controlling the encoder's output, beyond what BOCU-1 itself fixes.
+//!
+//! At present the only option is a reset policy. The encoder never needs to
+//! reset its state, but a stream with no C0 controls in it (a CJK paragraph,
+//! say, or a JSON blob) has no sync points either, so a reader that starts in
+//! the middle of it, or loses its place after an error, has to go all the way
+//! back to the start. A reset policy has the encoder inject reset markers
+//! (see the sync module) often enough to bound that distance, at the cost of
+//! a few bytes per marker and of the byte order of the output no longer
+//! matching the codepoint order of the text.
+
+use crate::delta_encoding::DeltaCoder;
+use crate::iter::EncodedChunk;
+use crate::sync::RESET_RUN_LEN;
+use crate::trailing_byte_selection::is_sync_byte;
+use crate::variable_length_code::{LEAD_BYTE_ASCII_SP, LEAD_BYTE_RESET};
+
+/// When the encoder should inject reset markers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ResetPolicy {
+    /// Never; the output is exactly the standard BOCU-1 encoding.
+    #[default]
+    Never,
+    /// Often enough that no byte is more than N bytes past the sync point
+    /// before it. N less than 8 (room for a 4-byte code unit plus a marker)
+    /// is treated as 8.
+    EveryBytes(usize),
+    /// Often enough that no char is more than N chars past the sync point
+    /// before it. N of 0 is treated as 1.
+    EveryChars(usize),
+    /// After the SP (or run of SPs) following each word. The marker is then
+    /// a single 0xFF byte, since it is known to be a lead byte after SP.
+    EachWord,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct EncoderOptions {
+    pub reset: ResetPolicy,
+}
+
+impl EncoderOptions {
+    pub fn new(reset: ResetPolicy) -> Self {
+        Self { reset: reset }
+    }
+}
+
+/// A DeltaCoder that injects reset markers according to a ResetPolicy,
+/// keeping track of how far the output has gone since the last sync point.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PolicyCoder {
+    coder: DeltaCoder,
+    policy: ResetPolicy,
+    bytes_since_sync: usize,
+    chars_since_sync: usize,
+    last_byte: Option<u8>,
+}
+
+impl PolicyCoder {
+    pub(crate) fn new(options: EncoderOptions) -> Self {
+        Self::with_state(options, DeltaCoder::new())
+    }
+
+    /// Resume encoding from `state`, counting from there as though it were a
+    /// sync point.
+    pub(crate) fn with_state(options: EncoderOptions, state: DeltaCoder) -> Self {
+        Self {
+            coder: state,
+            policy: options.reset,
+            bytes_since_sync: 0,
+            chars_since_sync: 0,
+            last_byte: None,
+        }
+    }
+
+    pub(crate) fn state(self: &Self) -> DeltaCoder {
+        self.coder
+    }
+
+    /// Encode `c`, returning the reset marker to emit before it, if one is
+    /// due, along with its code unit.
+    pub(crate) fn encode_char(self: &mut Self, c: char) -> (Option<EncodedChunk>, EncodedChunk) {
+        // A char that self-encodes as a sync byte starts a new segment of
+        // its own, so needs no marker before it.
+        let is_sync = c < ' ' && is_sync_byte(c as u8);
+        let mut marker = None;
+        if !is_sync && self.wants_reset(c) {
+            marker = Some(self.marker());
+            self.coder = DeltaCoder::new();
+            self.bytes_since_sync = 0;
+            self.chars_since_sync = 0;
+        }
+        let enc = self.coder.encode_char(c);
+        if is_sync {
+            self.bytes_since_sync = 0;
+            self.chars_since_sync = 0;
+        }
+        self.bytes_since_sync += enc.count;
+        self.chars_since_sync += 1;
+        self.last_byte = Some(enc.bytes[enc.count - 1]);
+        (marker, enc)
+    }
+
+    fn wants_reset(self: &Self, c: char) -> bool {
+        match self.policy {
+            ResetPolicy::Never => false,
+            ResetPolicy::EveryBytes(n) => {
+                // Leave room for the marker that ends this segment, too.
+                let mut probe = self.coder;
+                let len = probe.encoded_char_len(c);
+                self.bytes_since_sync != 0
+                    && self.bytes_since_sync + len + RESET_RUN_LEN > n.max(2 * RESET_RUN_LEN)
+            }
+            ResetPolicy::EveryChars(n) => self.chars_since_sync >= n.max(1),
+            ResetPolicy::EachWord => c != ' ' && self.last_byte == Some(LEAD_BYTE_ASCII_SP),
+        }
+    }
+
+    fn marker(self: &Self) -> EncodedChunk {
+        if self.last_byte == Some(LEAD_BYTE_ASCII_SP) {
+            EncodedChunk::new_single(LEAD_BYTE_RESET)
+        } else {
+            EncodedChunk {
+                bytes: [LEAD_BYTE_RESET; RESET_RUN_LEN],
+                count: RESET_RUN_LEN,
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Helpers for using encoded strings as keys in ordered stores, which rely on
+//! the byte order of encoder output being the codepoint order of the text.
+//!
+//! A range scan for all the keys with a given prefix needs a pair of byte
+//! bounds. The lower one is just the encoding of the prefix, since every
+//! extension's encoding begins with it. The upper one is less obvious,
+//! because what follows the prefix's bytes is coded relative to the state
+//! the prefix leaves behind, not appended to it in any fixed way. But it is
+//! still a single code unit for the next char, and code units are ordered by
+//! their lead bytes, so the largest lead byte any next char could have is
+//! that of the delta from the prefix's final state up to U+10FFFF.
+
+use crate::delta_encoding::DeltaCoder;
+use crate::variable_length_code::encode_delta;
+use alloc::vec::Vec;
+use core::ops::Bound;
+
+/// The byte range, [lo, hi), that holds exactly the encodings of the strings
+/// beginning with `prefix`. A string without that prefix never encodes into
+/// the range, and the upper bound is the least byte string above every
+/// encoding that is in it.
+pub fn prefix_range(prefix: &str) -> (Vec<u8>, Bound<Vec<u8>>) {
+    let mut coder = DeltaCoder::new();
+    let mut lo = Vec::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        lo.extend_from_slice(coder.encode_char(c).as_slice());
+    }
+    // The largest delta the next char could need is up to the last char;
+    // its lead byte is never above 0xFE, so there is always room for one
+    // more.
+    let max_delta = char::MAX as i32 - coder.state() as i32;
+    let max_lead = encode_delta(max_delta).bytes[0];
+    let mut hi = lo.clone();
+    hi.push(max_lead + 1);
+    (lo, Bound::Excluded(hi))
+}
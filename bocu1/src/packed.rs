@@ -14,25 +14,79 @@
 //! single scalar compare on packed strings in this form is sufficient to
 //! compare their unicode string values (at least at the crude codepoint level
 //! -- no UCA or CLDR logic at this level).
+//!
+//! The zero bytes padding out the low end of the scalar are what mark where
+//! the string ends, which leaves this plain layout no way to hold a NUL byte,
+//! and so no way to hold a string with a U+0000 in it (the only char whose
+//! encoding has a zero byte). Packing such a string fails, and a zero byte
+//! inside a packed string is a decoding error.
+//!
+//! For strings that may hold U+0000, there is also an escaped layout, in
+//! which every 0x00 byte of the encoding is written as 0x01 0x01, and every
+//! 0x01 byte as 0x01 0x02. The escape sequences sort in the same order as the
+//! bytes they stand for, and above the zero padding, so the escaped layout
+//! preserves codepoint order just as the plain one does. It costs a byte for
+//! each U+0000 and for each 0x01 trailing byte (which is rare). The two
+//! layouts agree on every string without either of those, but otherwise they
+//! differ, and a scalar must always be decoded in the layout it was packed in.
+//!
+//! The pack function and DecodePackedBOCU1 trait work on bare scalars. The
+//! PackedStr type wraps one, so that packed strings are kept apart from other
+//! integers and display as the text they hold.
 
 use crate::delta_encoding;
-use crate::DecodeError;
+use crate::encoded_len;
+use crate::iter::resync_len;
+use crate::variable_length_code;
 use crate::EncodeBOCU1;
+use crate::EncodedChunkIter;
+use crate::{DecodeError, DecodeErrorKind};
+use core::convert::{TryFrom, TryInto};
+use core::fmt;
+use core::mem;
+use core::ops::{BitAnd, BitOrAssign, Deref, RangeInclusive, ShlAssign, ShrAssign};
 use num_integer::Integer;
-use std::mem;
-use std::ops::{BitAnd, BitOrAssign, ShlAssign, ShrAssign};
-use try_from::TryInto;
 
-pub fn pack<IT, N>(i: &IT) -> Option<N>
+// The byte that begins an escape sequence in the escaped layout.
+const ESCAPE_BYTE: u8 = 0x01;
+
+/// The scalar types strings can be packed into and unpacked from: in
+/// practice the unsigned integers, of which u64 and u128 are the useful ones.
+pub trait PackScalar:
+    Copy
+    + Integer
+    + ShlAssign<usize>
+    + ShrAssign<usize>
+    + BitOrAssign<Self>
+    + BitAnd<Self, Output = Self>
+    + From<u8>
+    + TryInto<u8>
+{
+}
+
+impl<T> PackScalar for T where
+    T: Copy
+        + Integer
+        + ShlAssign<usize>
+        + ShrAssign<usize>
+        + BitOrAssign<T>
+        + BitAnd<T, Output = T>
+        + From<u8>
+        + TryInto<u8>
+{
+}
+
+// Pack a byte sequence into the high end of an N, zero-padding the rest.
+fn pack_iter<N, I>(bytes: I) -> Result<N, PackError>
 where
-    IT: EncodeBOCU1,
     N: Copy + Integer + ShlAssign<usize> + BitOrAssign<N> + From<u8>,
+    I: Iterator<Item = u8>,
 {
     let mut tmp: N = N::zero();
     let mut n: usize = mem::size_of::<N>();
-    for c in i.encode_bocu1() {
+    for c in bytes {
         if n == 0 {
-            return None;
+            return Err(PackError::TooLong);
         }
         tmp <<= 8;
         tmp |= N::from(c);
@@ -42,7 +96,105 @@ where
         tmp <<= 8;
         n -= 1;
     }
-    Some(tmp)
+    Ok(tmp)
+}
+
+/// Pack `i` in the plain layout, or return None if its encoding is too long
+/// for N or holds a NUL byte (that is, if it has a U+0000 in it).
+pub fn pack<IT, N>(i: &IT) -> Option<N>
+where
+    IT: EncodeBOCU1,
+    N: Copy + Integer + ShlAssign<usize> + BitOrAssign<N> + From<u8>,
+{
+    try_pack(i).ok()
+}
+
+/// As pack, but saying why a string can't be packed.
+pub fn try_pack<IT, N>(i: &IT) -> Result<N, PackError>
+where
+    IT: EncodeBOCU1,
+    N: Copy + Integer + ShlAssign<usize> + BitOrAssign<N> + From<u8>,
+{
+    let mut nul = false;
+    let n = pack_iter(i.encode_bocu1().inspect(|&b| nul |= b == 0))?;
+    if nul {
+        return Err(PackError::ContainsNul);
+    }
+    Ok(n)
+}
+
+/// Pack an already-encoded string, as pack would pack the string it holds,
+/// failing if it is too long for N or has a NUL byte. The bytes are packed
+/// as they are, without checking they are well-formed.
+pub fn pack_bytes<N>(b: &[u8]) -> Result<N, PackError>
+where
+    N: Copy + Integer + ShlAssign<usize> + BitOrAssign<N> + From<u8>,
+{
+    if b.contains(&0) {
+        return Err(PackError::ContainsNul);
+    }
+    pack_iter(b.iter().copied())
+}
+
+/// Pack as much of `s` as fits in an N, for use as an abbreviated sort key,
+/// returning the packed scalar and how many chars of `s` it holds whole.
+///
+/// Comparing two such scalars compares the first N bytes of the strings'
+/// encodings, so where the scalars differ the strings differ in the same
+/// order, and where they are equal (as they are for strings that differ only
+/// later on) the strings have to be compared in full. For that to work, the
+/// scalar holds exactly the first N bytes: if the last code unit that would
+/// be in it doesn't fit whole, as many of its bytes as fit are packed after
+/// the whole ones, rather than leaving zeros that would compare as though the
+/// string ended there. Such a scalar doesn't decode cleanly; if all of `s`
+/// was packed (the count is its length in chars) it is just what pack would
+/// produce, save that a NUL in `s` doesn't make it fail.
+pub fn pack_prefix<N>(s: &str) -> (N, usize)
+where
+    N: Copy + Integer + ShlAssign<usize> + BitOrAssign<N> + From<u8>,
+{
+    let size = mem::size_of::<N>();
+    let mut buf: [u8; 16] = [0; 16];
+    let mut len = 0;
+    let mut count = 0;
+    for enc in EncodedChunkIter::new(s.chars()) {
+        let unit = enc.as_slice();
+        let n = unit.len().min(size - len);
+        buf[len..len + n].copy_from_slice(&unit[..n]);
+        len += n;
+        if n < unit.len() {
+            break;
+        }
+        count += 1;
+        if len == size {
+            break;
+        }
+    }
+    let n = pack_iter(buf[..len].iter().copied()).expect("no more than N bytes");
+    (n, count)
+}
+
+/// Pack `i` in the escaped layout, which represents every string, failing
+/// only if its escaped encoding is too long for N.
+pub fn pack_escaped<IT, N>(i: &IT) -> Result<N, PackError>
+where
+    IT: EncodeBOCU1,
+    N: Copy + Integer + ShlAssign<usize> + BitOrAssign<N> + From<u8>,
+{
+    pack_iter(i.encode_bocu1().flat_map(|b| match b {
+        0x00 => [ESCAPE_BYTE, 0x01].into_iter().take(2),
+        0x01 => [ESCAPE_BYTE, 0x02].into_iter().take(2),
+        _ => [b, 0].into_iter().take(1),
+    }))
+}
+
+/// Whether pack can pack `s` into an N: whether it is short enough, once
+/// encoded, to fit, and has no U+0000 in it.
+pub fn fits_in<N>(s: &str) -> bool
+where
+    N: Copy + Integer + ShlAssign<usize> + BitOrAssign<N> + From<u8>,
+{
+    !s.contains('\0') && encoded_len(s) <= mem::size_of::<N>()
 }
 
 pub struct DecodePackedResultIter {
@@ -51,7 +203,14 @@ pub struct DecodePackedResultIter {
     // over fixed-size arrays at some point after const generics, though I don't
     // see a lot of benefit.
     buf: [u8; 16],
+    len: usize,
     rem: usize,
+    // Whether the bytes were unescaped from the escaped layout, in which case
+    // zero bytes are NULs rather than errors.
+    escaped: bool,
+    // The bad byte after an escape byte, if there was one. The bytes are only
+    // unescaped up to it, and it is reported once they have been decoded.
+    bad_escape: Option<u8>,
 }
 
 impl DecodePackedResultIter {
@@ -59,11 +218,11 @@ impl DecodePackedResultIter {
         assert!(rem <= 16);
         16 - rem
     }
-    pub fn range(rem: usize) -> std::ops::Range<usize> {
+    pub fn range(rem: usize) -> core::ops::Range<usize> {
         assert!(rem <= 16);
         (16 - rem)..16
     }
-    pub fn new<N>(mut n: N) -> Self
+    pub fn new<N>(n: N) -> Self
     where
         N: Copy + Integer + ShrAssign<usize> + BitAnd<N, Output = N> + From<u8> + TryInto<u8>,
     {
@@ -73,12 +232,12 @@ impl DecodePackedResultIter {
         //
         // That is, every packed value will have 0 or more low 00 NUL bytes and
         // then a sequence of non-NUL high bytes representing the original
-        // string. Packed strings do not allow or represent internal /
-        // non-terminal NUL bytes.
+        // string. Plain packed strings do not allow or represent internal /
+        // non-terminal NUL bytes: any that turn up are decoding errors.
         //
         // To unpack this back to a byte buffer, we walk down from end to start
-        // depositing bytes at the _end_ of the buffer, and counting the non-NUL
-        // bytes to form a "bytes remaining" count.
+        // depositing bytes at the _end_ of the buffer, and counting the bytes
+        // from the first non-NUL one on to form a "bytes remaining" count.
         //
         // This somewhat awkward arrangement means that we can incrementally
         // decode bytes _from_ this remaining-byte supply back to unicode
@@ -86,23 +245,87 @@ impl DecodePackedResultIter {
         // value, not a start/end pair. I don't much like this but I tried a few
         // other approaches and they read even more awkwardly. Feel free to
         // propose something else!
-        let mask = N::from(0xff_u8);
-        let mut buf: [u8; 16] = [0; 16];
-        let mut rem: usize = 0;
-        for _ in 0..mem::size_of::<N>() {
-            let byte: u8 = (n & mask).try_into().unwrap_or(0);
-            if byte != 0 {
-                rem += 1;
-                buf[Self::pos(rem)] = byte
+        let (buf, rem) = unpack_bytes(n);
+        Self {
+            state: delta_encoding::DeltaCoder::new(),
+            buf: buf,
+            len: rem,
+            rem: rem,
+            escaped: false,
+            bad_escape: None,
+        }
+    }
+
+    /// Decode a scalar packed in the escaped layout (see pack_escaped).
+    /// Error offsets count bytes of the unescaped encoding.
+    pub fn new_escaped<N>(n: N) -> Self
+    where
+        N: Copy + Integer + ShrAssign<usize> + BitAnd<N, Output = N> + From<u8> + TryInto<u8>,
+    {
+        let (raw, raw_len) = unpack_bytes(n);
+        let mut tmp: [u8; 16] = [0; 16];
+        let mut len = 0;
+        let mut bad_escape = None;
+        let mut i = Self::pos(raw_len);
+        while i < raw.len() {
+            let mut b = raw[i];
+            i += 1;
+            if b == ESCAPE_BYTE {
+                // A final escape byte is followed by the zero padding, if
+                // there is any, so report it as followed by a zero.
+                match raw.get(i).copied().unwrap_or(0) {
+                    0x01 => b = 0x00,
+                    0x02 => b = 0x01,
+                    other => {
+                        bad_escape = Some(other);
+                        break;
+                    }
+                }
+                i += 1;
             }
-            n >>= 8;
+            tmp[len] = b;
+            len += 1;
         }
+        let mut buf: [u8; 16] = [0; 16];
+        buf[Self::range(len)].copy_from_slice(&tmp[..len]);
         Self {
             state: delta_encoding::DeltaCoder::new(),
             buf: buf,
-            rem: rem,
+            len: len,
+            rem: len,
+            escaped: true,
+            bad_escape: bad_escape,
         }
     }
+
+    fn bad_byte(self: &Self, b: u8) -> DecodeError {
+        DecodeError::new(
+            DecodeErrorKind::BadPackedByte(b),
+            self.len - self.rem,
+            self.state.state(),
+        )
+    }
+}
+
+// Deposit the bytes of `n` above its zero padding at the end of a buffer, as
+// described in DecodePackedResultIter::new, returning the buffer and how many
+// there were.
+fn unpack_bytes<N>(mut n: N) -> ([u8; 16], usize)
+where
+    N: Copy + Integer + ShrAssign<usize> + BitAnd<N, Output = N> + From<u8> + TryInto<u8>,
+{
+    let mask = N::from(0xff_u8);
+    let mut buf: [u8; 16] = [0; 16];
+    let mut rem: usize = 0;
+    for _ in 0..mem::size_of::<N>() {
+        let byte: u8 = (n & mask).try_into().unwrap_or(0);
+        if byte != 0 || rem != 0 {
+            rem += 1;
+            buf[DecodePackedResultIter::pos(rem)] = byte;
+        }
+        n >>= 8;
+    }
+    (buf, rem)
 }
 
 impl Iterator for DecodePackedResultIter {
@@ -110,7 +333,14 @@ impl Iterator for DecodePackedResultIter {
     fn next(self: &mut Self) -> Option<Result<char, DecodeError>> {
         loop {
             if self.rem == 0 {
-                return None;
+                let b = self.bad_escape.take()?;
+                return Some(Err(self.bad_byte(b)));
+            }
+            if !self.escaped && self.buf[Self::pos(self.rem)] == 0 {
+                let e = self.bad_byte(0);
+                self.rem -= 1;
+                self.state = delta_encoding::DeltaCoder::new();
+                return Some(Err(e));
             }
             match self.state.decode_char(&self.buf[Self::range(self.rem)]) {
                 Ok((None, rest)) => self.rem = rest.len(),
@@ -119,6 +349,9 @@ impl Iterator for DecodePackedResultIter {
                     return Some(Ok(c));
                 }
                 Err(e) => {
+                    let e = e.offset_by(self.len - self.rem);
+                    self.rem -= resync_len(&self.buf[Self::range(self.rem)]);
+                    self.state = delta_encoding::DeltaCoder::new();
                     return Some(Err(e));
                 }
             }
@@ -139,6 +372,15 @@ impl DecodePackedIter {
             inner: DecodePackedResultIter::new(n),
         }
     }
+
+    pub fn new_escaped<N>(n: N) -> Self
+    where
+        N: Copy + Integer + ShrAssign<usize> + BitAnd<N, Output = N> + From<u8> + TryInto<u8>,
+    {
+        Self {
+            inner: DecodePackedResultIter::new_escaped(n),
+        }
+    }
 }
 
 impl Iterator for DecodePackedIter {
@@ -153,6 +395,7 @@ impl Iterator for DecodePackedIter {
 
 pub trait DecodePackedBOCU1 {
     fn decode_packed_bocu1(self: &Self) -> DecodePackedIter;
+    fn decode_escaped_packed_bocu1(self: &Self) -> DecodePackedIter;
 }
 
 impl<T> DecodePackedBOCU1 for T
@@ -162,4 +405,313 @@ where
     fn decode_packed_bocu1(self: &Self) -> DecodePackedIter {
         DecodePackedIter::new(*self)
     }
+    fn decode_escaped_packed_bocu1(self: &Self) -> DecodePackedIter {
+        DecodePackedIter::new_escaped(*self)
+    }
+}
+
+// Neighbouring packed strings
+// --------------------------
+//
+// Packed strings sort in codepoint order, so the next packed string after a
+// given one is the next string in codepoint order that still fits, and
+// likewise for the previous one and the greatest one with a given prefix.
+// Finding them comes down to asking which chars fit in the room left after
+// some prefix. Those are the self-encoded ones up to SP, which take a byte
+// each, and the chars above SP whose delta from the prefix's state has a
+// short enough code: an interval around the state, from the lead-byte tables
+// in variable_length_code. (U+0000 also takes a byte, but can't be packed.)
+
+// The most chars a packed string can hold: one per byte.
+const MAX_PACKED_CHARS: usize = 16;
+
+// A string of up to MAX_PACKED_CHARS chars, held on the stack, along with
+// the coder state and encoded length after each of its prefixes.
+struct PackedChars {
+    chars: [char; MAX_PACKED_CHARS],
+    states: [delta_encoding::DeltaCoder; MAX_PACKED_CHARS + 1],
+    lens: [usize; MAX_PACKED_CHARS + 1],
+    count: usize,
+}
+
+impl PackedChars {
+    fn new() -> Self {
+        Self {
+            chars: ['\u{0}'; MAX_PACKED_CHARS],
+            states: [delta_encoding::DeltaCoder::new(); MAX_PACKED_CHARS + 1],
+            lens: [0; MAX_PACKED_CHARS + 1],
+            count: 0,
+        }
+    }
+
+    // The chars packed in `n`, if it holds exactly what pack would produce
+    // for them.
+    fn unpack<N: PackScalar>(n: N) -> Option<Self> {
+        let mut pc = Self::new();
+        for r in DecodePackedResultIter::new(n) {
+            pc.push(r.ok()?);
+        }
+        if pc.pack::<N>() == Some(n) {
+            Some(pc)
+        } else {
+            None
+        }
+    }
+
+    fn push(self: &mut Self, c: char) {
+        let mut state = self.states[self.count];
+        self.lens[self.count + 1] = self.lens[self.count] + state.encoded_char_len(c);
+        self.states[self.count + 1] = state;
+        self.chars[self.count] = c;
+        self.count += 1;
+    }
+
+    fn truncate(self: &mut Self, count: usize) {
+        self.count = count;
+    }
+
+    fn len(self: &Self) -> usize {
+        self.lens[self.count]
+    }
+
+    fn pack<N: PackScalar>(self: &Self) -> Option<N> {
+        pack(&self.chars[..self.count].iter())
+    }
+
+    // Extend the string with the greatest chars that fit in `size` bytes, so
+    // that it becomes the greatest string of that size with its prefix.
+    fn extend_to_max(self: &mut Self, size: usize) {
+        while self.len() < size {
+            let room = size - self.len();
+            let c = prev_fitting(self.states[self.count], room, MAX_CHAR_PLUS_ONE)
+                .expect("U+0020 always fits");
+            self.push(c);
+        }
+    }
+}
+
+const MAX_CHAR_PLUS_ONE: u32 = char::MAX as u32 + 1;
+
+// The chars above SP that fit in `room` bytes after the state `state`, as an
+// interval (which may be empty, or include surrogate codepoints).
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::cast_possible_truncation)]
+fn fitting_interval(state: delta_encoding::DeltaCoder, room: usize) -> (u32, u32) {
+    let prev = state.state() as i64;
+    let deltas = variable_length_code::delta_range_within_len(room);
+    let lo = (prev + i64::from(*deltas.start())).max(0x21);
+    let hi = (prev + i64::from(*deltas.end())).min(i64::from(char::MAX as u32));
+    (lo as u32, hi as u32)
+}
+
+// The least packable char above `after` that fits in `room` bytes.
+fn next_fitting(state: delta_encoding::DeltaCoder, room: usize, after: u32) -> Option<char> {
+    let mut c = after + 1;
+    if c > 0x20 {
+        let (lo, hi) = fitting_interval(state, room);
+        c = c.max(lo);
+        if (0xD800..=0xDFFF).contains(&c) {
+            c = 0xE000;
+        }
+        if c > hi {
+            return None;
+        }
+    }
+    core::char::from_u32(c)
+}
+
+// The greatest packable char below `before` that fits in `room` bytes.
+fn prev_fitting(state: delta_encoding::DeltaCoder, room: usize, before: u32) -> Option<char> {
+    let mut c = before.checked_sub(1)?;
+    if c > 0x20 {
+        let (lo, hi) = fitting_interval(state, room);
+        c = c.min(hi);
+        if (0xD800..=0xDFFF).contains(&c) {
+            c = 0xD7FF;
+        }
+        if c < lo {
+            c = c.min(0x20);
+        }
+    }
+    if c == 0 {
+        return None;
+    }
+    core::char::from_u32(c)
+}
+
+/// The range of packed scalars that holds exactly the packed strings that
+/// begin with `prefix`, from the prefix itself up to the greatest packed
+/// string that begins with it. None if the prefix itself can't be packed.
+pub fn packed_prefix_range<N: PackScalar>(prefix: &str) -> Option<RangeInclusive<N>> {
+    let lo = pack(&prefix)?;
+    let mut pc = PackedChars::new();
+    for c in prefix.chars() {
+        pc.push(c);
+    }
+    pc.extend_to_max(mem::size_of::<N>());
+    let hi = pc.pack().expect("extended to fit");
+    Some(lo..=hi)
+}
+
+/// The least packed string greater than the one packed in `n`. None if there
+/// is none, or if `n` is not a packed string (not something pack could have
+/// produced).
+pub fn packed_successor<N: PackScalar>(n: N) -> Option<N> {
+    let mut pc = PackedChars::unpack(n)?;
+    let size = mem::size_of::<N>();
+    if pc.len() < size {
+        // There is room for the least char of all.
+        pc.push('\u{1}');
+        return pc.pack();
+    }
+    // Otherwise, bump the last char that can be bumped, and drop the rest.
+    for i in (0..pc.count).rev() {
+        let room = size - pc.lens[i];
+        if let Some(c) = next_fitting(pc.states[i], room, pc.chars[i] as u32) {
+            pc.truncate(i);
+            pc.push(c);
+            return pc.pack();
+        }
+    }
+    None
+}
+
+/// The greatest packed string less than the one packed in `n`. None if there
+/// is none (that is, `n` is the empty string), or if `n` is not a packed
+/// string.
+pub fn packed_predecessor<N: PackScalar>(n: N) -> Option<N> {
+    let mut pc = PackedChars::unpack(n)?;
+    let i = pc.count.checked_sub(1)?;
+    let room = mem::size_of::<N>() - pc.lens[i];
+    let prev = prev_fitting(pc.states[i], room, pc.chars[i] as u32);
+    pc.truncate(i);
+    // With a lesser last char, the greatest string is as long as it can be;
+    // without, it is the string minus its last char.
+    if let Some(c) = prev {
+        pc.push(c);
+        pc.extend_to_max(mem::size_of::<N>());
+    }
+    pc.pack()
+}
+
+/// Why a string could not be packed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackError {
+    /// The string's encoding is longer than the scalar type.
+    TooLong,
+    /// The string has a U+0000 in it, which the plain layout can't hold.
+    ContainsNul,
+}
+
+impl fmt::Display for PackError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PackError::TooLong => write!(f, "string is too long to pack"),
+            PackError::ContainsNul => write!(f, "string with a NUL can't be packed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PackError {}
+
+/// A string packed into a scalar of type N, as by pack. Unlike the bare
+/// scalar, this can't be mixed up with an ordinary integer, and it compares,
+/// hashes and formats as the string it holds. Comparison is still a single
+/// scalar compare.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct PackedStr<N> {
+    n: N,
+}
+
+impl<N: PackScalar> PackedStr<N> {
+    /// Pack `i` in the plain layout (see try_pack).
+    pub fn new<IT: EncodeBOCU1>(i: &IT) -> Result<Self, PackError> {
+        Ok(Self { n: try_pack(i)? })
+    }
+
+    /// Wrap a scalar already holding a packed string.
+    pub fn from_scalar(n: N) -> Self {
+        Self { n: n }
+    }
+
+    pub fn to_scalar(self: &Self) -> N {
+        self.n
+    }
+
+    /// The encoded bytes of the string.
+    pub fn as_bytes(self: &Self) -> PackedBytes {
+        let (buf, len) = unpack_bytes(self.n);
+        PackedBytes { buf: buf, len: len }
+    }
+
+    /// The length of the string's encoding, in bytes.
+    pub fn len(self: &Self) -> usize {
+        self.as_bytes().len()
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.n == N::zero()
+    }
+
+    pub fn chars(self: &Self) -> DecodePackedIter {
+        DecodePackedIter::new(self.n)
+    }
+}
+
+impl<N: PackScalar> TryFrom<&str> for PackedStr<N> {
+    type Error = PackError;
+    fn try_from(s: &str) -> Result<Self, PackError> {
+        Self::new(&s)
+    }
+}
+
+impl<N: PackScalar> fmt::Display for PackedStr<N> {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        use core::fmt::Write;
+        for c in self.chars() {
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+impl<N: PackScalar> fmt::Debug for PackedStr<N> {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        // As str's Debug, but without needing to allocate a String.
+        use core::fmt::Write;
+        f.write_char('"')?;
+        for c in self.chars() {
+            for e in c.escape_debug() {
+                f.write_char(e)?;
+            }
+        }
+        f.write_char('"')
+    }
+}
+
+/// The encoded bytes of a PackedStr, held by value.
+#[derive(Clone, Copy)]
+pub struct PackedBytes {
+    buf: [u8; 16],
+    len: usize,
+}
+
+impl Deref for PackedBytes {
+    type Target = [u8];
+    fn deref(self: &Self) -> &[u8] {
+        &self.buf[DecodePackedResultIter::range(self.len)]
+    }
+}
+
+impl AsRef<[u8]> for PackedBytes {
+    fn as_ref(self: &Self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for PackedBytes {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
 }
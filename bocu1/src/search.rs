@@ -0,0 +1,228 @@
+//! Substring search directly on encoded bytes, following the strategy in the
+//! crate docs: byte-search for candidates, then decode to confirm them.
+//!
+//! A needle's encoding depends on the state its first char is coded against,
+//! so the needle as a whole can't be searched for. But once its first
+//! state-setting char has been coded, the state is known, and the rest of the
+//! needle (its "tail") has a fixed encoding. So the search is for the tail's
+//! bytes, and each candidate is then checked by decoding the haystack up to
+//! it: it must begin on a code unit boundary, with the decoder in the state
+//! the tail was encoded against, just after the needle's head. The decoding
+//! runs forward from one candidate to the next, skipping ahead to any sync
+//! point in between, so the whole search decodes each byte at most once.
+//!
+//! As with any comparison of encoded bytes, the haystack is assumed to be
+//! plain encoder output: a match that spans an injected reset marker (see
+//! EncoderOptions) has different bytes to the needle's, and is not found.
+
+use crate::delta_encoding::{normalized_prev, DeltaCoder};
+use crate::iter::resync_len;
+use crate::sync::is_sync_point;
+use crate::variable_length_code::LEAD_BYTE_ASCII_SP;
+use crate::{DecodeResultIter, EncodedChunkIter};
+use alloc::vec::Vec;
+use memchr::memmem;
+
+// A position in the haystack up to which it has been decoded, along with the
+// last char decoded and where its code unit began, if that is known.
+#[derive(Clone, Copy)]
+struct Cursor {
+    offset: usize,
+    state: DeltaCoder,
+    last: Option<(char, usize)>,
+}
+
+impl Cursor {
+    fn at(offset: usize) -> Self {
+        Self {
+            offset: offset,
+            state: DeltaCoder::new(),
+            last: None,
+        }
+    }
+
+    // Decode the code unit at the cursor. Malformed units are skipped, and
+    // forget the last char.
+    fn step(self: &mut Self, h: &[u8]) {
+        let start = self.offset;
+        match self.state.decode_char(&h[start..]) {
+            Ok((c, rest)) => {
+                self.offset = h.len() - rest.len();
+                if let Some(c) = c {
+                    self.last = Some((c, start));
+                }
+            }
+            Err(_) => {
+                *self = Self::at(start + resync_len(&h[start..]));
+            }
+        }
+    }
+}
+
+/// An iterator over the byte offsets of the non-overlapping matches of a
+/// needle in an encoded haystack.
+pub struct FindIter<'h> {
+    haystack: &'h [u8],
+    // The needle is head_sp SPs, then head (if it has any non-SP char), then
+    // the chars encoded as tail, starting from tail_state.
+    head_sp: usize,
+    head: Option<char>,
+    tail: memmem::Finder<'static>,
+    tail_state: DeltaCoder,
+    // Where to look for the next candidate from, and where the last match
+    // ended (which the next must begin at or after).
+    pos: usize,
+    end: usize,
+    cursor: Cursor,
+    done: bool,
+}
+
+impl<'h> FindIter<'h> {
+    pub fn new(haystack: &'h [u8], needle: &str) -> FindIter<'h> {
+        let head_sp = needle
+            .bytes()
+            .take_while(|&b| b == LEAD_BYTE_ASCII_SP)
+            .count();
+        let mut rest = needle[head_sp..].chars();
+        let head = rest.next();
+        let tail_state = match head {
+            Some(c) if c > ' ' => DeltaCoder::from_state(normalized_prev(c)).expect("normalized"),
+            _ => DeltaCoder::new(),
+        };
+        let tail: Vec<u8> = match head {
+            None => needle.as_bytes().to_vec(),
+            Some(_) => EncodedChunkIter::with_state(rest, tail_state)
+                .flat_map(|enc| enc.bytes.into_iter().take(enc.count))
+                .collect(),
+        };
+        FindIter {
+            haystack: haystack,
+            head_sp: head_sp,
+            head: head,
+            tail: memmem::Finder::new(&tail).into_owned(),
+            tail_state: tail_state,
+            pos: 0,
+            end: 0,
+            cursor: Cursor::at(0),
+            done: false,
+        }
+    }
+
+    // Decode up to offset p, skipping to the last sync point short of p if
+    // there is one ahead of the cursor (short of p, so as to still decode the
+    // char before p).
+    fn advance_to(self: &mut Self, p: usize) {
+        let h = self.haystack;
+        if let Some(s) = (self.cursor.offset + 1..p)
+            .rev()
+            .find(|&i| is_sync_point(h, i))
+        {
+            self.cursor = Cursor::at(s);
+        }
+        while self.cursor.offset < p {
+            self.cursor.step(h);
+        }
+    }
+
+    // If the cursor is at p, just after the needle's head and in the tail's
+    // state, and the match begins at or after end, where the match begins.
+    fn head_before(self: &Self, p: usize) -> Option<usize> {
+        let h = self.haystack;
+        let (c, u) = self.cursor.last?;
+        let start = u.checked_sub(self.head_sp)?;
+        let matched = self.cursor.offset == p
+            && self.cursor.state == self.tail_state
+            && Some(c) == self.head
+            && start >= self.end
+            && h[start..u].iter().all(|&b| b == LEAD_BYTE_ASCII_SP);
+        if matched {
+            Some(start)
+        } else {
+            None
+        }
+    }
+}
+
+impl Iterator for FindIter<'_> {
+    type Item = usize;
+    fn next(self: &mut Self) -> Option<usize> {
+        let h = self.haystack;
+        if self.done {
+            return None;
+        }
+        match self.head {
+            // An empty needle matches at every char boundary.
+            None if self.head_sp == 0 => {
+                let at = self.cursor.offset;
+                // Move on to the end of the next char, if there is one.
+                let mut more = false;
+                while !more && self.cursor.offset < h.len() {
+                    self.cursor.step(h);
+                    more = self.cursor.last.is_some_and(|(_, u)| u >= at);
+                }
+                self.done = !more;
+                return Some(at);
+            }
+            // SP bytes are unambiguous, so a needle of all SPs can be matched
+            // as bytes, with no need to decode anything.
+            None => {
+                let i = self.tail.find(&h[self.pos..]);
+                self.done = i.is_none();
+                let start = self.pos + i?;
+                self.pos = start + self.head_sp;
+                return Some(start);
+            }
+            Some(_) => (),
+        }
+        if self.tail.needle().is_empty() {
+            // With no tail to search for, look at every char for the head.
+            while self.cursor.offset < h.len() {
+                self.cursor.step(h);
+                if let Some(start) = self.head_before(self.cursor.offset) {
+                    self.end = self.cursor.offset;
+                    return Some(start);
+                }
+            }
+        } else {
+            while let Some(i) = self.tail.find(&h[self.pos..]) {
+                let p = self.pos + i;
+                if self.cursor.offset <= p {
+                    self.advance_to(p);
+                    if let Some(start) = self.head_before(p) {
+                        self.pos = p + self.tail.needle().len();
+                        self.end = self.pos;
+                        return Some(start);
+                    }
+                }
+                self.pos = p + 1;
+            }
+        }
+        self.done = true;
+        None
+    }
+}
+
+/// The byte offset of the first match of `needle` in `haystack`, at the start
+/// of a code unit.
+pub fn find(haystack: &[u8], needle: &str) -> Option<usize> {
+    FindIter::new(haystack, needle).next()
+}
+
+/// The byte offsets of the non-overlapping matches of `needle` in `haystack`.
+pub fn find_iter<'h>(haystack: &'h [u8], needle: &str) -> FindIter<'h> {
+    FindIter::new(haystack, needle)
+}
+
+pub fn contains(haystack: &[u8], needle: &str) -> bool {
+    find(haystack, needle).is_some()
+}
+
+/// Whether `haystack` begins with `prefix`. Only as much of the haystack as
+/// the prefix covers is decoded; unlike the other functions here, this works
+/// on any well-formed haystack, reset markers and all.
+pub fn starts_with(haystack: &[u8], prefix: &str) -> bool {
+    let mut chars = DecodeResultIter::new(haystack);
+    prefix
+        .chars()
+        .all(|c| matches!(chars.next(), Some(Ok(d)) if c == d))
+}
@@ -0,0 +1,99 @@
+//! Cutting and joining encoded BOCU-1 without decoding and re-encoding all of
+//! it.
+//!
+//! Two encoded strings can't simply be concatenated, because the second was
+//! encoded starting from the initial delta-coder state rather than from the
+//! state at the end of the first. Likewise a substring can't simply be cut
+//! out, because it was encoded starting from whatever state the text before
+//! it left behind. But two encodings of the same text from different states
+//! only differ up to its first state-setting code unit: after a C0 control or
+//! a reset byte, both are in the initial state, and after any other char both
+//! are in the state that char normalizes to. So it is enough to re-encode
+//! that one unit, and copy the rest verbatim.
+
+use crate::delta_encoding::DeltaCoder;
+use crate::iter::{validate_with_state, DecodeResultIter};
+use crate::variable_length_code::{LEAD_BYTE_ASCII_SP, LEAD_BYTE_RESET};
+use crate::DecodeError;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Append `b`, encoded starting from state `from`, to `out`, whose encoder
+/// is in state `state`, re-encoding only what needs it. Returns the state
+/// `out` is left in, if that is known without decoding the rest of `b` (that
+/// is, if `b` was all SPs), or else None. Error offsets count from the start
+/// of `b`.
+pub(crate) fn append_bocu1(
+    out: &mut Vec<u8>,
+    state: DeltaCoder,
+    b: &[u8],
+    from: DeltaCoder,
+) -> Result<Option<DeltaCoder>, DecodeError> {
+    // SPs leave the state alone, so are the same in both encodings.
+    let sp = b.iter().take_while(|&&x| x == LEAD_BYTE_ASCII_SP).count();
+    out.extend_from_slice(&b[..sp]);
+    let rest = &b[sp..];
+    match rest.first() {
+        None => return Ok(Some(state)),
+        // C0 controls and reset bytes reset the state, so from them on
+        // everything is the same.
+        Some(&lead) if lead < LEAD_BYTE_ASCII_SP || lead == LEAD_BYTE_RESET => {
+            out.extend_from_slice(rest);
+        }
+        Some(_) => {
+            let mut orig = from;
+            let (c, tail) = orig.decode_char(rest).map_err(|e| e.offset_by(sp))?;
+            let c = c.expect("only a reset byte decodes to no char");
+            let mut joined = state;
+            out.extend_from_slice(joined.encode_char(c).as_slice());
+            out.extend_from_slice(tail);
+        }
+    }
+    Ok(None)
+}
+
+/// Concatenate two encoded strings, producing the encoding of the
+/// concatenation of the texts they hold. `a` is decoded to find its final
+/// coder state, but only the first char of `b` is re-encoded. Error offsets
+/// count from the start of `a` (so an error in `b` is at `a.len()` plus its
+/// offset in `b`).
+pub fn concat(a: &[u8], b: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let (_, state) = validate_with_state(a)?;
+    let mut out = Vec::with_capacity(a.len() + b.len() + 4);
+    out.extend_from_slice(a);
+    append_bocu1(&mut out, state, b, DeltaCoder::new()).map_err(|e| e.offset_by(a.len()))?;
+    Ok(out)
+}
+
+/// Extract the chars at char offsets `chars` of `buf` as a string of their
+/// own. The text before the range is decoded to find where it starts, and
+/// the range itself to find where it ends, but only its first char is
+/// re-encoded. Chars past the end of `buf` are ignored, as they would be by
+/// chars().skip().take(). Error offsets count from the start of `buf`.
+pub fn substring(buf: &[u8], chars: Range<usize>) -> Result<Vec<u8>, DecodeError> {
+    let mut iter = DecodeResultIter::new(buf);
+    let mut start = (buf.len(), DeltaCoder::new());
+    let mut end = buf.len();
+    let mut n = 0;
+    loop {
+        if n == chars.start {
+            start = (iter.offset(), iter.state());
+        }
+        if n >= chars.start && n >= chars.end {
+            end = iter.offset();
+            break;
+        }
+        match iter.next() {
+            None => break,
+            Some(r) => {
+                r?;
+            }
+        }
+        n += 1;
+    }
+    let (start, from) = start;
+    let mut out = Vec::with_capacity(end - start + 4);
+    append_bocu1(&mut out, DeltaCoder::new(), &buf[start..end], from)
+        .map_err(|e| e.offset_by(start))?;
+    Ok(out)
+}
@@ -0,0 +1,113 @@
+//! Splitting encoded text into lines or other records without decoding it.
+//!
+//! Every C0 control is self-encoded and resets the delta-coder state, so the
+//! bytes after one are coded exactly as they would be at the start of a
+//! string: each piece of an encoded text split at a C0 control is a valid
+//! BOCU-1 string in its own right, holding exactly that piece of the text.
+//!
+//! Finding the separators is a byte search when the separator is one of the
+//! C0 controls that can never be a trailing byte (see
+//! trailing_byte_selection::is_sync_byte), which includes LF and CR. The
+//! others can also turn up inside multibyte code units, so for them the
+//! search has to step from one code unit to the next.
+
+use crate::trailing_byte_selection::is_sync_byte;
+use crate::variable_length_code::{code_unit_len, LEAD_BYTE_ASCII_SP};
+
+const ASCII_LF: u8 = 0x0a;
+const ASCII_CR: u8 = 0x0d;
+
+// The offset of the first code unit in `b` that is the separator `sep`.
+fn find_sep(b: &[u8], sep: u8) -> Option<usize> {
+    if is_sync_byte(sep) {
+        return memchr::memchr(sep, b);
+    }
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] == sep {
+            return Some(i);
+        }
+        i += code_unit_len(b[i]);
+    }
+    None
+}
+
+/// An iterator over the pieces of an encoded text between occurrences of a
+/// C0 control, as with str::split.
+pub struct SplitC0<'a> {
+    rest: Option<&'a [u8]>,
+    sep: u8,
+}
+
+impl<'a> SplitC0<'a> {
+    pub fn new(b: &'a [u8], sep: u8) -> SplitC0<'a> {
+        assert!(sep < LEAD_BYTE_ASCII_SP, "separator must be a C0 control");
+        SplitC0 {
+            rest: Some(b),
+            sep: sep,
+        }
+    }
+}
+
+impl<'a> Iterator for SplitC0<'a> {
+    type Item = &'a [u8];
+    fn next(self: &mut Self) -> Option<&'a [u8]> {
+        let rest = self.rest?;
+        match find_sep(rest, self.sep) {
+            None => {
+                self.rest = None;
+                Some(rest)
+            }
+            Some(i) => {
+                self.rest = Some(&rest[i + 1..]);
+                Some(&rest[..i])
+            }
+        }
+    }
+}
+
+/// An iterator over the lines of an encoded text, as with str::lines: lines
+/// end with LF or CR LF, which are not included, and a final line ending does
+/// not start another (empty) line. A bare CR does not end a line.
+pub struct Lines<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Lines<'a> {
+    pub fn new(b: &'a [u8]) -> Lines<'a> {
+        Lines { rest: b }
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a [u8];
+    fn next(self: &mut Self) -> Option<&'a [u8]> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        match find_sep(self.rest, ASCII_LF) {
+            None => {
+                let line = self.rest;
+                self.rest = &[];
+                Some(line)
+            }
+            Some(i) => {
+                let line = &self.rest[..i];
+                self.rest = &self.rest[i + 1..];
+                // CR is self-encoded and never a trailing byte, so it can be
+                // taken off the end without looking at what comes before.
+                Some(line.strip_suffix(&[ASCII_CR]).unwrap_or(line))
+            }
+        }
+    }
+}
+
+/// Split an encoded text at each occurrence of the C0 control `sep`.
+pub fn split_c0(b: &[u8], sep: u8) -> SplitC0<'_> {
+    SplitC0::new(b, sep)
+}
+
+/// Split an encoded text into lines.
+pub fn lines(b: &[u8]) -> Lines<'_> {
+    Lines::new(b)
+}
@@ -0,0 +1,279 @@
+//! This module provides string types that hold BOCU-1 bytes which are known to
+//! decode without error: an owned Bocu1String and an unsized, borrowed
+//! Bocu1Str. They relate to each other the way String and str (or OsString
+//! and OsStr) do, and are checked on construction so that the decoding
+//! accessors on them never have to report errors.
+//!
+//! Both types compare and hash by their encoded bytes. Because the
+//! variable-length code preserves order, byte order on encoder-produced
+//! strings is the same as codepoint order on the text they hold, so sorting
+//! or keying on these types never needs to decode anything. (Bytes that were
+//! not produced by this encoder -- say, with injected 0xFF reset bytes -- are
+//! still valid, but only compare meaningfully against similarly-produced
+//! strings.)
+
+use crate::delta_encoding::DeltaCoder;
+use crate::iter::validate_with_state;
+use crate::search;
+use crate::splice::append_bocu1;
+use crate::{DecodeError, DoubleEndedDecodeIter};
+use alloc::borrow::{Borrow, ToOwned};
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::ops::Deref;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Bocu1Str {
+    bytes: [u8],
+}
+
+impl Bocu1Str {
+    /// Borrow `b` as a Bocu1Str, if it is entirely well-formed BOCU-1.
+    pub fn from_bytes(b: &[u8]) -> Result<&Bocu1Str, DecodeError> {
+        validate_with_state(b)?;
+        Ok(Self::from_bytes_unchecked(b))
+    }
+
+    /// Borrow `b` as a Bocu1Str without checking it.
+    ///
+    /// Nothing unsafe can come of `b` not being well-formed BOCU-1, but the
+    /// accessors on Bocu1Str assume it is: given malformed bytes, they may
+    /// yield U+FFFD replacement characters or panic.
+    pub fn from_bytes_unchecked(b: &[u8]) -> &Bocu1Str {
+        // SAFETY: Bocu1Str is a repr(transparent) wrapper around [u8], so it
+        // has the same layout and pointer metadata, and the returned
+        // reference borrows from `b` with the same lifetime.
+        unsafe { &*(::core::ptr::from_ref::<[u8]>(b) as *const Bocu1Str) }
+    }
+
+    pub fn as_bytes(self: &Self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The length of the string in encoded bytes (not in chars).
+    pub fn len(self: &Self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The byte offset of the first match of `needle` (see search::find).
+    pub fn find(self: &Self, needle: &str) -> Option<usize> {
+        search::find(&self.bytes, needle)
+    }
+
+    pub fn contains(self: &Self, needle: &str) -> bool {
+        search::contains(&self.bytes, needle)
+    }
+
+    pub fn starts_with(self: &Self, prefix: &str) -> bool {
+        search::starts_with(&self.bytes, prefix)
+    }
+
+    /// The chars of the string, from either end.
+    pub fn chars(self: &Self) -> DoubleEndedDecodeIter<'_> {
+        DoubleEndedDecodeIter::new(&self.bytes)
+    }
+}
+
+impl AsRef<[u8]> for Bocu1Str {
+    fn as_ref(self: &Self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl fmt::Display for Bocu1Str {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        use core::fmt::Write;
+        for c in self.chars() {
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Bocu1Str {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string(), f)
+    }
+}
+
+impl ToOwned for Bocu1Str {
+    type Owned = Bocu1String;
+    fn to_owned(self: &Self) -> Bocu1String {
+        // The end state is recomputed rather than stored, since a Bocu1Str
+        // may be any valid slice of bytes.
+        Bocu1String::from_bytes(self.bytes.to_vec()).expect("Bocu1Str holds valid BOCU-1")
+    }
+}
+
+/// An owned, growable BOCU-1 string. Alongside the bytes it keeps the delta
+/// coder state at the end of the string, so that further chars can be
+/// appended without re-decoding what is already there.
+#[derive(Clone)]
+pub struct Bocu1String {
+    bytes: Vec<u8>,
+    state: DeltaCoder,
+}
+
+impl Bocu1String {
+    pub fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            state: DeltaCoder::new(),
+        }
+    }
+
+    /// Take ownership of `b` as a Bocu1String, if it is entirely well-formed
+    /// BOCU-1.
+    pub fn from_bytes(b: Vec<u8>) -> Result<Self, DecodeError> {
+        let (_, state) = validate_with_state(&b)?;
+        Ok(Self {
+            bytes: b,
+            state: state,
+        })
+    }
+
+    pub fn as_bocu1_str(self: &Self) -> &Bocu1Str {
+        Bocu1Str::from_bytes_unchecked(&self.bytes)
+    }
+
+    /// The coder state at the end of the string.
+    pub fn state(self: &Self) -> DeltaCoder {
+        self.state
+    }
+
+    pub fn into_bytes(self: Self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn push(self: &mut Self, c: char) {
+        let enc = self.state.encode_char(c);
+        self.bytes.extend_from_slice(enc.as_slice());
+    }
+
+    pub fn push_str(self: &mut Self, s: &str) {
+        self.extend(s.chars());
+    }
+
+    /// Append an already-encoded string, re-encoding only its first char
+    /// (see splice::concat).
+    pub fn push_bocu1(self: &mut Self, s: &Bocu1Str) {
+        let end = append_bocu1(&mut self.bytes, self.state, s.as_bytes(), DeltaCoder::new())
+            .expect("Bocu1Str holds valid BOCU-1");
+        // Past its first state-setting unit, the appended string is in the
+        // same state it would be on its own.
+        self.state = end.unwrap_or_else(|| {
+            let (_, state) =
+                validate_with_state(s.as_bytes()).expect("Bocu1Str holds valid BOCU-1");
+            state
+        });
+    }
+}
+
+impl Default for Bocu1String {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for Bocu1String {
+    type Target = Bocu1Str;
+    fn deref(self: &Self) -> &Bocu1Str {
+        self.as_bocu1_str()
+    }
+}
+
+impl Borrow<Bocu1Str> for Bocu1String {
+    fn borrow(self: &Self) -> &Bocu1Str {
+        self.as_bocu1_str()
+    }
+}
+
+impl AsRef<Bocu1Str> for Bocu1String {
+    fn as_ref(self: &Self) -> &Bocu1Str {
+        self.as_bocu1_str()
+    }
+}
+
+impl AsRef<[u8]> for Bocu1String {
+    fn as_ref(self: &Self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+// Comparison and hashing go through Bocu1Str, so that they agree with it for
+// the sake of Borrow; the coder state is a function of the bytes anyway.
+
+impl PartialEq for Bocu1String {
+    fn eq(self: &Self, other: &Self) -> bool {
+        self.as_bocu1_str() == other.as_bocu1_str()
+    }
+}
+
+impl Eq for Bocu1String {}
+
+impl PartialOrd for Bocu1String {
+    fn partial_cmp(self: &Self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Bocu1String {
+    fn cmp(self: &Self, other: &Self) -> Ordering {
+        self.as_bocu1_str().cmp(other.as_bocu1_str())
+    }
+}
+
+impl Hash for Bocu1String {
+    fn hash<H: Hasher>(self: &Self, state: &mut H) {
+        self.as_bocu1_str().hash(state);
+    }
+}
+
+impl fmt::Display for Bocu1String {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_bocu1_str(), f)
+    }
+}
+
+impl fmt::Debug for Bocu1String {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_bocu1_str(), f)
+    }
+}
+
+impl From<&str> for Bocu1String {
+    fn from(s: &str) -> Self {
+        s.chars().collect()
+    }
+}
+
+impl From<&Bocu1Str> for Bocu1String {
+    fn from(s: &Bocu1Str) -> Self {
+        s.to_owned()
+    }
+}
+
+impl FromIterator<char> for Bocu1String {
+    fn from_iter<IT: IntoIterator<Item = char>>(iter: IT) -> Self {
+        let mut s = Self::new();
+        s.extend(iter);
+        s
+    }
+}
+
+impl Extend<char> for Bocu1String {
+    fn extend<IT: IntoIterator<Item = char>>(self: &mut Self, iter: IT) {
+        for c in iter {
+            self.push(c);
+        }
+    }
+}
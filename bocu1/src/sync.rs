@@ -0,0 +1,61 @@
+//! Finding the points in a BOCU-1 byte stream where decoding can begin with a
+//! fresh delta-coder state, without knowing anything about the bytes before
+//! them.
+//!
+//! There are two kinds of such sync point. The first is an unambiguous sync
+//! byte (see trailing_byte_selection::is_sync_byte): a C0 control that can
+//! never be a trailing byte, so must be a self-encoded char, which resets the
+//! state. Decoding restarts _at_ such a byte.
+//!
+//! The second is a LEAD_BYTE_RESET (0xFF) that is known to be in lead-byte
+//! position. A lone 0xFF proves nothing, since it is also a valid trailing
+//! byte, but it is known to be a lead byte when it follows SP or a sync byte
+//! (neither of which can start or continue a multibyte code unit), and when
+//! it is the 4th (or later) of a run of 0xFF bytes (since a code unit has at
+//! most 3 trailing bytes, and the encoder itself never produces a 0xFF lead
+//! byte). Decoding restarts just _after_ such a byte. These are the reset
+//! markers that EncoderOptions can ask the encoder to inject.
+
+use crate::trailing_byte_selection::is_sync_byte;
+use crate::variable_length_code::{LEAD_BYTE_ASCII_SP, LEAD_BYTE_RESET};
+
+/// The length of the run of 0xFF bytes that is recognizable as a reset
+/// wherever it occurs.
+pub const RESET_RUN_LEN: usize = 4;
+
+/// Whether decoding can begin at offset `i` of `buf` with a fresh state. The
+/// start of the buffer always qualifies, and its end never does.
+pub fn is_sync_point(buf: &[u8], i: usize) -> bool {
+    if i >= buf.len() {
+        return false;
+    }
+    if i == 0 || is_sync_byte(buf[i]) {
+        return true;
+    }
+    if buf[i - 1] != LEAD_BYTE_RESET {
+        return false;
+    }
+    if i == 1 {
+        return true;
+    }
+    let before = buf[i - 2];
+    before == LEAD_BYTE_ASCII_SP
+        || is_sync_byte(before)
+        || (i >= RESET_RUN_LEN
+            && buf[i - RESET_RUN_LEN..i]
+                .iter()
+                .all(|&b| b == LEAD_BYTE_RESET))
+}
+
+/// The first sync point in `buf` at or after offset `from`, if any.
+pub fn next_sync_point(buf: &[u8], from: usize) -> Option<usize> {
+    (from..buf.len()).find(|&i| is_sync_point(buf, i))
+}
+
+/// The last sync point in `buf` at or before offset `at`; offset 0, if the
+/// buffer is not empty, is always one.
+pub fn prev_sync_point(buf: &[u8], at: usize) -> Option<usize> {
+    (0..=at.min(buf.len()))
+        .rev()
+        .find(|&i| is_sync_point(buf, i))
+}
@@ -1,6 +1,21 @@
-use crate::packed::{pack, DecodePackedBOCU1};
+// Quickcheck properties take their inputs by value, and the packed literals
+// below are grouped by byte with a gap between each 32-bit word.
+#![allow(clippy::needless_pass_by_value)]
+#![allow(clippy::unusual_byte_groupings)]
+// Lints that arrived after the older tests below were written.
+#![allow(clippy::needless_borrow)]
+#![allow(clippy::needless_borrows_for_generic_args)]
+#![allow(clippy::let_unit_value)]
+#![allow(clippy::ignored_unit_patterns)]
+#![allow(clippy::semicolon_if_nothing_returned)]
+
+use crate::packed::{pack, try_pack, DecodePackedBOCU1, DecodePackedResultIter};
 use crate::DecodeBOCU1;
 use crate::EncodeBOCU1;
+use crate::{
+    decode_lossy, from_bocu1, validate, DecodeErrorKind, DecodeLossyIter, DecodeResultIter,
+};
+use crate::{Bocu1Reader, Bocu1Str, Bocu1String, Bocu1Writer, IncrementalDecoder};
 use std::vec::Vec;
 extern crate env_logger;
 extern crate quickcheck;
@@ -68,7 +83,8 @@ fn test_lex_order_50k_random_packed_string_pairs() {
         // more like 16, but this is worst-case and we're hooked up to a fuzzer, so..)
         let _ = env_logger::try_init();
 
-        // Also note: NUL bytes are not allowed / respected at all in packed forms.
+        // Also note: NUL bytes can't be held in the plain packed layout (the
+        // escaped one is checked separately below).
         let sc1: Vec<char> = s1.chars().filter(|x| *x != '\u{0}').collect();
         let sc2: Vec<char> = s2.chars().filter(|x| *x != '\u{0}').collect();
         let sc1_trunc = if sc1.len() > 4 { &sc1[0..4] } else { &sc1[..] };
@@ -181,6 +197,1588 @@ fn test_multi() {
     );
 }
 
+fn first_error(b: &[u8]) -> crate::DecodeError {
+    DecodeResultIter::new(b)
+        .find_map(Result::err)
+        .expect("expected a decode error")
+}
+
+#[test]
+fn test_error_truncated() {
+    let mut v: Vec<u8> = "ab學".encode_bocu1().collect();
+    v.pop();
+    let e = first_error(&v);
+    assert_eq!(e.kind(), DecodeErrorKind::TruncatedInput);
+    assert_eq!(e.offset(), 2);
+    assert_eq!(e.prev(), '\u{40}');
+}
+
+#[test]
+fn test_error_trail_byte() {
+    let e = first_error(&[0xb8, 0xd0, 0x0a]);
+    assert_eq!(e.kind(), DecodeErrorKind::TrailByteOutOfRange(0x0a));
+    assert_eq!(e.offset(), 1);
+    assert_eq!(e.prev(), '\u{40}');
+}
+
+#[test]
+fn test_error_char_delta() {
+    let e = first_error(&[0x20, 0x21, 0x01, 0x01, 0x01]);
+    assert!(matches!(e.kind(), DecodeErrorKind::CharDeltaOutOfRange(_)));
+    assert_eq!(e.offset(), 1);
+    assert_eq!(
+        e.to_string(),
+        "invalid BOCU-1 at byte offset 1: delta -14536567 does not reach \
+         a unicode scalar value (previous-value state U+0040)"
+    );
+    let boxed: Box<dyn std::error::Error> = Box::new(e);
+    assert!(boxed.to_string().starts_with("invalid BOCU-1"));
+}
+
+#[test]
+fn test_error_packed_offset() {
+    let p: u64 = 0x_b8_d0_0a_00__00_00_00_00_u64;
+    let e = DecodePackedResultIter::new(p)
+        .find_map(Result::err)
+        .expect("expected a decode error");
+    assert_eq!(e.kind(), DecodeErrorKind::TrailByteOutOfRange(0x0a));
+    assert_eq!(e.offset(), 1);
+}
+
+#[test]
+fn test_result_iter_resyncs() {
+    // A bad trail byte, then an LF to resync on, then more text.
+    let bad: &[u8] = &[0xb8, 0xd0, 0x0a, 0xb5];
+    let res: Vec<Result<char, crate::DecodeError>> = DecodeResultIter::new(bad).collect();
+    assert_eq!(res.len(), 4);
+    assert_eq!(res[0], Ok('h'));
+    assert!(res[1].is_err());
+    assert_eq!(res[2], Ok('\n'));
+    assert_eq!(res[3], Ok('e'));
+}
+
+#[test]
+fn test_lossy() {
+    let bad: &[u8] = &[0xb8, 0xd0, 0x0a, 0xb5];
+    assert_eq!(decode_lossy(bad), ("h\u{FFFD}\ne".to_string(), 1));
+
+    // Truncation at the end becomes a single replacement.
+    let mut v: Vec<u8> = "ab學".encode_bocu1().collect();
+    v.pop();
+    let mut iter = DecodeLossyIter::new(&v);
+    let s: String = iter.by_ref().collect();
+    assert_eq!(s, "ab\u{FFFD}");
+    assert_eq!(iter.replacements(), 1);
+
+    // Valid input passes through untouched.
+    let v: Vec<u8> = "hello εφαρμογών\n".encode_bocu1().collect();
+    assert_eq!(decode_lossy(&v), ("hello εφαρμογών\n".to_string(), 0));
+}
+
+#[test]
+fn test_reset_byte() {
+    // 0xFF is never emitted by the encoder, but when it occurs it resets the
+    // previous-value state just like a C0 control.
+    let mut v: Vec<u8> = "εφ".encode_bocu1().collect();
+    v.push(0xff);
+    v.push(0xb8);
+    let s: String = v.as_slice().decode_bocu1().collect();
+    assert_eq!(s, "εφh");
+}
+
+#[test]
+fn test_validate() {
+    let v: Vec<u8> = "hello εφαρμογών\n學而".encode_bocu1().collect();
+    assert_eq!(validate(&v), Ok(18));
+    assert_eq!(from_bocu1(&v).as_deref(), Ok("hello εφαρμογών\n學而"));
+    assert_eq!(validate(&[]), Ok(0));
+
+    let e = validate(&v[..v.len() - 1]).unwrap_err();
+    assert_eq!(e.kind(), DecodeErrorKind::TruncatedInput);
+    assert_eq!(e.offset(), v.len() - 2);
+    assert_eq!(from_bocu1(&v[..v.len() - 1]), Err(e));
+}
+
+#[test]
+fn test_bocu1_string() {
+    let mut s = Bocu1String::from("hello ");
+    s.push_str("εφαρμογών");
+    s.extend("\nабв".chars());
+    let v: Vec<u8> = "hello εφαρμογών\nабв".encode_bocu1().collect();
+    assert_eq!(s.as_bytes(), v.as_slice());
+    assert_eq!(s.len(), v.len());
+    assert_eq!(s.to_string(), "hello εφαρμογών\nабв");
+    assert_eq!(format!("{:?}", s), "\"hello εφαρμογών\\nабв\"");
+    assert_eq!(s.chars().count(), 19);
+
+    let borrowed = Bocu1Str::from_bytes(&v).unwrap();
+    assert_eq!(borrowed, &*s);
+    assert_eq!(borrowed.to_owned(), s);
+    assert!(Bocu1Str::from_bytes(&v[..v.len() - 1]).is_ok());
+    assert!(Bocu1Str::from_bytes(&[0xd0]).is_err());
+    assert!(Bocu1String::from_bytes(vec![0xb8, 0xd0, 0x0a]).is_err());
+
+    // A string rebuilt from its bytes carries on encoding from the same state.
+    let mut t = Bocu1String::from_bytes(v).unwrap();
+    t.push('г');
+    s.push('г');
+    assert_eq!(t, s);
+}
+
+#[test]
+fn test_bocu1_string_order() {
+    let mut words = vec!["zebra", "學而", "apple", "εφ", "", "app", "\u{10FFFF}"];
+    let mut encoded: Vec<Bocu1String> = words.iter().map(|w| Bocu1String::from(*w)).collect();
+    words.sort_unstable();
+    encoded.sort();
+    let decoded: Vec<String> = encoded.iter().map(ToString::to_string).collect();
+    assert_eq!(decoded, words);
+}
+
+// A reader that hands out its input a few bytes at a time, so that code units
+// straddle the reads.
+struct TrickleReader<'a> {
+    input: &'a [u8],
+    step: usize,
+}
+
+impl std::io::Read for TrickleReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.step.min(buf.len()).min(self.input.len());
+        buf[..n].copy_from_slice(&self.input[..n]);
+        self.input = &self.input[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_reader() {
+    use std::io::{BufRead, Read};
+    let text = "hello εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之";
+    let v: Vec<u8> = text.encode_bocu1().collect();
+    for step in 1..6 {
+        let mut out = String::new();
+        let mut r = Bocu1Reader::new(TrickleReader {
+            input: &v,
+            step: step,
+        });
+        r.read_to_string(&mut out).unwrap();
+        assert_eq!(out, text);
+    }
+    let r = Bocu1Reader::new(TrickleReader { input: &v, step: 3 });
+    let lines: Vec<String> = r.lines().map(Result::unwrap).collect();
+    assert_eq!(lines, text.lines().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_reader_errors() {
+    use std::io::Read;
+    let v: Vec<u8> = "ab學".encode_bocu1().collect();
+    let mut out = String::new();
+    let e = Bocu1Reader::new(&v[..v.len() - 1])
+        .read_to_string(&mut out)
+        .unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    let inner = e.into_inner().unwrap();
+    let de = inner.downcast_ref::<crate::DecodeError>().unwrap();
+    assert_eq!(de.kind(), DecodeErrorKind::TruncatedInput);
+    assert_eq!(de.offset(), 2);
+
+    let mut r = Bocu1Reader::new(TrickleReader {
+        input: &[0xb8, 0xb5, 0xd0, 0x0a],
+        step: 1,
+    });
+    let e = r.read_to_string(&mut out).unwrap_err();
+    let de = *e
+        .get_ref()
+        .unwrap()
+        .downcast_ref::<crate::DecodeError>()
+        .unwrap();
+    assert_eq!(de.kind(), DecodeErrorKind::TrailByteOutOfRange(0x0a));
+    assert_eq!(de.offset(), 2);
+}
+
+// The text and errors from reading `input` a few bytes at a time through a
+// Bocu1Reader, with the text between errors run together.
+fn read_past_errors(input: &[u8], step: usize, buf_len: usize) -> Vec<Result<String, usize>> {
+    use std::io::Read;
+    let mut r = Bocu1Reader::new(TrickleReader {
+        input: input,
+        step: step,
+    });
+    let mut res: Vec<Result<String, usize>> = Vec::new();
+    let mut text = Vec::new();
+    let mut buf = vec![0_u8; buf_len];
+    // Enough reads to get through the input many times over, should the
+    // reader get stuck repeating itself.
+    for _ in 0..input.len() * 16 {
+        match r.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => text.extend_from_slice(&buf[..n]),
+            Err(e) => {
+                let de = *e
+                    .get_ref()
+                    .unwrap()
+                    .downcast_ref::<crate::DecodeError>()
+                    .unwrap();
+                if !text.is_empty() {
+                    res.push(Ok(String::from_utf8(text.split_off(0)).unwrap()));
+                }
+                res.push(Err(de.offset()));
+            }
+        }
+    }
+    if !text.is_empty() {
+        res.push(Ok(String::from_utf8(text).unwrap()));
+    }
+    res
+}
+
+#[test]
+fn test_reader_reads_past_errors() {
+    use std::io::BufRead;
+    let mut v: Vec<u8> = "abc".encode_bocu1().collect();
+    v.extend_from_slice(&[0xd0, 0x0a]);
+    v.extend("xyz".encode_bocu1());
+    // An error in a run with no sync points, which has to be skipped up to
+    // the reset marker after it.
+    v.extend("hi".encode_bocu1());
+    v.extend_from_slice(&[0xfe, 0xff, 0xff, 0xff]);
+    v.extend("學而時習之".encode_bocu1());
+    v.extend_from_slice(&[0xff; 4]);
+    v.extend("ok".encode_bocu1());
+
+    // Reading the stream should give what decoding it all at once does.
+    let mut expected: Vec<Result<String, usize>> = Vec::new();
+    for r in DecodeResultIter::new(&v) {
+        match (r, expected.last_mut()) {
+            (Ok(c), Some(Ok(s))) => s.push(c),
+            (Ok(c), _) => expected.push(Ok(c.to_string())),
+            (Err(e), _) => expected.push(Err(e.offset())),
+        }
+    }
+    assert_eq!(expected.len(), 5);
+    assert_eq!(expected[0], Ok("abc".to_string()));
+    assert_eq!(expected[1], Err(3));
+    assert_eq!(expected[2], Ok("\nxyzhi".to_string()));
+    assert_eq!(expected[4], Ok("ok".to_string()));
+    for step in 1..7 {
+        for buf_len in 1..4 {
+            assert_eq!(read_past_errors(&v, step, buf_len), expected);
+        }
+    }
+
+    // Lines that fail to decode can be skipped (which would never end, as
+    // the lint warns, if the reader kept returning the same error).
+    let r = Bocu1Reader::new(TrickleReader { input: &v, step: 2 });
+    #[allow(clippy::lines_filter_map_ok)]
+    let lines: Vec<String> = r.lines().filter_map(Result::ok).collect();
+    assert_eq!(lines.last().unwrap(), "ok");
+}
+
+// A reader that gives out its input in one read, then would block.
+struct BlockingReader<'a> {
+    input: &'a [u8],
+}
+
+impl std::io::Read for BlockingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.input.is_empty() {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        let n = buf.len().min(self.input.len());
+        buf[..n].copy_from_slice(&self.input[..n]);
+        self.input = &self.input[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn test_reader_decodes_past_errors_before_reading() {
+    use std::io::BufRead;
+    let mut v: Vec<u8> = "hi".encode_bocu1().collect();
+    v.extend_from_slice(&[0xd0, 0x0a]);
+    v.extend("hello\n".encode_bocu1());
+    let mut r = Bocu1Reader::new(BlockingReader { input: &v });
+    let mut line = String::new();
+    let e = r.read_line(&mut line).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    // The text after the error was read along with it, so it comes out
+    // without the inner reader being asked for more.
+    let mut line = String::new();
+    r.read_line(&mut line).unwrap();
+    assert_eq!(line, "\n");
+    let mut line = String::new();
+    r.read_line(&mut line).unwrap();
+    assert_eq!(line, "hello\n");
+    let e = r.read_line(&mut String::new()).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::WouldBlock);
+}
+
+// A writer that only ever accepts one byte per call.
+struct TrickleWriter(Vec<u8>);
+
+impl std::io::Write for TrickleWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.extend_from_slice(&buf[..buf.len().min(1)]);
+        Ok(buf.len().min(1))
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_writer() {
+    use std::io::Write;
+    let text = "hello εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之";
+    let v: Vec<u8> = text.encode_bocu1().collect();
+    for step in 1..6 {
+        let mut w = Bocu1Writer::new(TrickleWriter(Vec::new()));
+        for piece in text.as_bytes().chunks(step) {
+            w.write_all(piece).unwrap();
+        }
+        assert_eq!(w.finish().unwrap().0, v);
+    }
+
+    let mut out = Vec::new();
+    assert_eq!(
+        crate::write_encoded_chars(text, &mut TrickleWriter(Vec::new())).unwrap(),
+        v.len()
+    );
+    crate::write_encoded_chars(text, &mut out).unwrap();
+    assert_eq!(out, v);
+}
+
+#[test]
+fn test_writer_errors() {
+    use std::io::Write;
+    let mut w = Bocu1Writer::new(Vec::new());
+    w.write_all("hi ".as_bytes()).unwrap();
+    w.write_all(&"學".as_bytes()[..2]).unwrap();
+    assert_eq!(
+        w.flush().unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    w.write_all(&"學".as_bytes()[2..]).unwrap();
+    w.flush().unwrap();
+    let expected: Vec<u8> = "hi 學".encode_bocu1().collect();
+    assert_eq!(w.get_ref(), &expected);
+    w.write_all(&"學".as_bytes()[..1]).unwrap();
+    assert!(w.finish().is_err());
+
+    let mut w = Bocu1Writer::new(Vec::new());
+    assert_eq!(w.write(b"ab\xffcd").unwrap(), 2);
+    assert!(w.write(b"\xffcd").is_err());
+}
+
+#[test]
+fn test_incremental() {
+    let text = "hello εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之";
+    let v: Vec<u8> = text.encode_bocu1().collect();
+    for step in 1..6 {
+        let mut d = IncrementalDecoder::new();
+        let mut out = String::new();
+        for chunk in v.chunks(step) {
+            for r in d.push_slice(chunk) {
+                out.push(r.unwrap());
+            }
+        }
+        assert!(d.finish().is_ok());
+        assert_eq!(out, text);
+    }
+}
+
+#[test]
+fn test_incremental_errors() {
+    let v: Vec<u8> = "ab學".encode_bocu1().collect();
+    let mut d = IncrementalDecoder::new();
+    let (last, init) = v.split_last().unwrap();
+    let out: Result<String, _> = d.push_slice(init).collect();
+    assert_eq!(out.as_deref(), Ok("ab"));
+    assert!(d.is_pending());
+    let e = d.finish().unwrap_err();
+    assert_eq!(e.kind(), DecodeErrorKind::TruncatedInput);
+    assert_eq!(e.offset(), 2);
+    assert!(!d.is_pending());
+
+    // Feeding the final byte instead completes the char.
+    let mut d = IncrementalDecoder::new();
+    let _ = d.push_slice(init).count();
+    assert_eq!(d.push(*last), Ok(Some('學')));
+
+    // A bad trailing byte is reported as soon as it arrives.
+    let mut d = IncrementalDecoder::new();
+    assert_eq!(d.push(0xb8), Ok(Some('h')));
+    assert_eq!(d.push(0xd0), Ok(None));
+    let e = d.push(0x0a).unwrap_err();
+    assert_eq!(e.kind(), DecodeErrorKind::TrailByteOutOfRange(0x0a));
+    assert_eq!(e.offset(), 1);
+    assert_eq!(d.push(0xb5), Ok(Some('e')));
+
+    // A sync byte that cuts a code unit short is left to start the next one,
+    // as DecodeResultIter would resume there.
+    let v = [0xd0, 0x0a, 0xb8];
+    let mut d = IncrementalDecoder::new();
+    assert_eq!(d.push(0xd0), Ok(None));
+    let e = d.push(0x0a).unwrap_err();
+    assert_eq!(e.kind(), DecodeErrorKind::TrailByteOutOfRange(0x0a));
+    assert_eq!(e.offset(), 0);
+    assert_eq!(d.consumed(), 1);
+    assert_eq!(d.push(0x0a), Ok(Some('\n')));
+    let mut d = IncrementalDecoder::new();
+    let out: Vec<_> = d.push_slice(&v).collect();
+    let expected: Vec<_> = DecodeResultIter::new(&v).collect();
+    assert_eq!(out, expected);
+    assert_eq!(out.len(), 3);
+    assert_eq!(out[1], Ok('\n'));
+    assert_eq!(decode_lossy(&v), ("\u{FFFD}\nh".to_string(), 1));
+}
+
+#[test]
+fn test_buffer_encode() {
+    use crate::buffer::{CoderResult, Encoder};
+    let text = "hello εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之\u{10FFFF}";
+    let v: Vec<u8> = text.encode_bocu1().collect();
+    for size in 4..9 {
+        let mut enc = Encoder::new();
+        let mut dst = vec![0_u8; size];
+        let mut src = text;
+        let mut out = Vec::new();
+        loop {
+            let (res, read, written) = enc.encode_from_utf8(src, &mut dst, true);
+            out.extend_from_slice(&dst[..written]);
+            src = &src[read..];
+            if res == CoderResult::InputEmpty {
+                break;
+            }
+            assert!(written > size - 4);
+        }
+        assert_eq!(out, v);
+    }
+}
+
+#[test]
+fn test_buffer_decode() {
+    use crate::buffer::{Decoder, DecoderResult};
+    let text = "hello εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之\u{10FFFF}";
+    let v: Vec<u8> = text.encode_bocu1().collect();
+    for size in 4..9 {
+        let mut dec = Decoder::new();
+        let mut dst8 = vec![0_u8; size];
+        let mut out = Vec::new();
+        for src in v.chunks(3) {
+            let mut src = src;
+            loop {
+                let (res, read, written) = dec.decode_to_utf8(src, &mut dst8, false);
+                out.extend_from_slice(&dst8[..written]);
+                src = &src[read..];
+                if res == DecoderResult::InputEmpty {
+                    break;
+                }
+                assert_eq!(res, DecoderResult::OutputFull);
+            }
+        }
+        assert_eq!(
+            dec.decode_to_utf8(&[], &mut dst8, true).0,
+            DecoderResult::InputEmpty
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), text);
+
+        let mut dec = Decoder::new();
+        let mut dst16 = vec![0_u16; size / 2];
+        let mut src = v.as_slice();
+        let mut out = Vec::new();
+        loop {
+            let (res, read, written) = dec.decode_to_utf16(src, &mut dst16, true);
+            out.extend_from_slice(&dst16[..written]);
+            src = &src[read..];
+            if res == DecoderResult::InputEmpty {
+                break;
+            }
+        }
+        assert_eq!(String::from_utf16(&out).unwrap(), text);
+    }
+
+    // An incomplete code unit is malformed only once the input is done.
+    let v: Vec<u8> = "學".encode_bocu1().collect();
+    let mut dec = Decoder::new();
+    let mut dst = [0_u8; 16];
+    assert_eq!(
+        dec.decode_to_utf8(&v[..1], &mut dst, false),
+        (DecoderResult::InputEmpty, 1, 0)
+    );
+    match dec.decode_to_utf8(&[], &mut dst, true) {
+        (DecoderResult::Malformed(e), 0, 0) => {
+            assert_eq!(e.kind(), DecodeErrorKind::TruncatedInput);
+        }
+        r => panic!("unexpected {:?}", r),
+    }
+
+    // A sync byte that cuts a code unit short is left unread, so the char it
+    // encodes isn't lost.
+    let v = [0xd0, 0x0a, 0xb8];
+    let mut dec = Decoder::new();
+    match dec.decode_to_utf8(&v, &mut dst, true) {
+        (DecoderResult::Malformed(e), 1, 0) => {
+            assert_eq!(e.kind(), DecodeErrorKind::TrailByteOutOfRange(0x0a));
+        }
+        r => panic!("unexpected {:?}", r),
+    }
+    assert_eq!(
+        dec.decode_to_utf8(&v[1..], &mut dst, true),
+        (DecoderResult::InputEmpty, 2, 2)
+    );
+    assert_eq!(&dst[..2], b"\nh");
+    let mut dec = Decoder::new();
+    let mut dst16 = [0_u16; 4];
+    let (_, read, _) = dec.decode_to_utf16(&v, &mut dst16, true);
+    assert_eq!(
+        dec.decode_to_utf16(&v[read..], &mut dst16, true),
+        (DecoderResult::InputEmpty, 2, 2)
+    );
+    assert_eq!(String::from_utf16(&dst16[..2]).unwrap(), "\nh");
+}
+
+#[test]
+fn test_encoded_len() {
+    use crate::packed::fits_in;
+    use crate::{encoded_len, max_encoded_len};
+    for s in &[
+        "",
+        "hello",
+        "hello εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之",
+        "\u{10FFFF}\u{0}\u{10FFFF}",
+    ] {
+        let n = s.encode_bocu1().count();
+        assert_eq!(encoded_len(s), n);
+        assert!(Some(n) <= max_encoded_len(s.chars().count()));
+    }
+    assert_eq!(max_encoded_len(usize::MAX / 4), Some(usize::MAX / 4 * 4));
+    assert_eq!(max_encoded_len(usize::MAX / 4 + 1), None);
+    assert!(fits_in::<u64>("hello"));
+    assert!(pack::<_, u64>(&"hello").is_some());
+    assert!(fits_in::<u64>("hellowor"));
+    assert!(!fits_in::<u64>("hellowor!"));
+    assert!(pack::<_, u64>(&"hellowor!").is_none());
+    assert!(!fits_in::<u64>("a\0"));
+    assert!(pack::<_, u64>(&"a\0").is_none());
+    assert!(fits_in::<u128>("εφαρμογών"));
+}
+
+#[test]
+fn test_encoded_len_random_strings() {
+    use self::quickcheck::*;
+    fn check_one(s: String) -> bool {
+        crate::encoded_len(&s) == s.as_str().encode_bocu1().count()
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(String) -> bool);
+}
+
+// Check that every sync point in `v` (the encoding of `s`) really is one:
+// decoding from it gives a suffix of `s`. Returns the sync points.
+fn check_sync_points(s: &str, v: &[u8]) -> Vec<usize> {
+    use crate::sync::{is_sync_point, next_sync_point};
+    let points: Vec<usize> = (0..v.len()).filter(|&i| is_sync_point(v, i)).collect();
+    assert_eq!(next_sync_point(v, 0), points.first().copied());
+    for &i in &points {
+        let tail = from_bocu1(&v[i..]).unwrap();
+        assert!(s.ends_with(&tail), "bad sync point {} in {:?}", i, s);
+    }
+    points
+}
+
+#[test]
+fn test_reset_policies() {
+    use crate::sync::prev_sync_point;
+    use crate::{write_encoded_chars_with_options, EncodedChunkIter, EncoderOptions, ResetPolicy};
+    use std::io::Write;
+    let cjk = "學而時習之，不亦說乎？有朋自遠方來，不亦樂乎？".repeat(20);
+    let mixed = "hello εφαρμογών आजकल воплощению HELLOコンニチワ 學而時習之 ".repeat(20);
+    for s in &[cjk.as_str(), mixed.as_str()] {
+        let plain: Vec<u8> = s.encode_bocu1().collect();
+        for &policy in &[
+            ResetPolicy::Never,
+            ResetPolicy::EveryBytes(0),
+            ResetPolicy::EveryBytes(64),
+            ResetPolicy::EveryChars(10),
+            ResetPolicy::EachWord,
+        ] {
+            let options = EncoderOptions::new(policy);
+            let mut v = Vec::new();
+            let n = write_encoded_chars_with_options(s, &mut v, options).unwrap();
+            assert_eq!(n, v.len());
+            let chunks: Vec<u8> = EncodedChunkIter::with_options(s.chars(), options)
+                .flat_map(|enc| enc.as_slice().to_vec())
+                .collect();
+            assert_eq!(chunks, v);
+            let mut w = Bocu1Writer::with_options(TrickleWriter(Vec::new()), options);
+            w.write_all(s.as_bytes()).unwrap();
+            assert_eq!(w.finish().unwrap().0, v);
+
+            assert_eq!(from_bocu1(&v).unwrap(), *s);
+            let points = check_sync_points(s, &v);
+            match policy {
+                ResetPolicy::Never => assert_eq!(v, plain),
+                ResetPolicy::EveryBytes(n) => {
+                    let n = n.max(8);
+                    for i in 0..v.len() {
+                        assert!(i - prev_sync_point(&v, i).unwrap() < n);
+                    }
+                }
+                ResetPolicy::EveryChars(n) => {
+                    for w in points.windows(2) {
+                        let seg = from_bocu1(&v[w[0]..w[1]]).unwrap();
+                        assert!(seg.chars().count() <= n);
+                    }
+                }
+                ResetPolicy::EachWord => {
+                    let words = s.split(' ').filter(|w| !w.is_empty()).count();
+                    assert_eq!(points.len(), words);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_reset_every_bytes_random_strings() {
+    use self::quickcheck::*;
+    use crate::sync::prev_sync_point;
+    use crate::{EncodedChunkIter, EncoderOptions, ResetPolicy};
+    fn check_one(s: String, n: u8) -> bool {
+        let n = usize::from(n).max(8);
+        let options = EncoderOptions::new(ResetPolicy::EveryBytes(n));
+        let v: Vec<u8> = EncodedChunkIter::with_options(s.chars(), options)
+            .flat_map(|enc| enc.as_slice().to_vec())
+            .collect();
+        check_sync_points(&s, &v);
+        from_bocu1(&v).unwrap() == s
+            && (0..v.len()).all(|i| i - prev_sync_point(&v, i).unwrap() < n)
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(String, u8) -> bool);
+}
+
+fn check_index(s: &str, v: &[u8], interval: usize) -> bool {
+    use crate::index::IndexFormatError;
+    use crate::Bocu1Index;
+    let chars: Vec<char> = s.chars().collect();
+    let index = Bocu1Index::new(v, interval).unwrap();
+    let index2 = Bocu1Index::from_bytes(&index.to_bytes()).unwrap();
+    assert_eq!(index, index2);
+    let bytes = index.to_bytes();
+    assert_eq!(
+        Bocu1Index::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(IndexFormatError::WrongLength)
+    );
+    assert_eq!(index.char_at(&v[..v.len() / 2], 0), None);
+    index.char_len() == chars.len()
+        && (0..=chars.len()).all(|n| {
+            let off = index.byte_offset_of(v, n).unwrap();
+            from_bocu1(&v[..off]).unwrap().chars().count() == n
+                && index.char_at(v, n) == chars.get(n).copied()
+                && index.slice_chars(v, n / 2..n).unwrap()
+                    == chars[n / 2..n].iter().collect::<String>()
+        })
+        && index.byte_offset_of(v, chars.len() + 1).is_none()
+        && index.slice_chars(v, 0..chars.len() + 1).is_none()
+}
+
+#[test]
+fn test_index_from_bad_bytes() {
+    use crate::index::IndexFormatError;
+    use crate::Bocu1Index;
+    let v: Vec<u8> = "hello world".encode_bocu1().collect();
+    let bytes = Bocu1Index::new(&v, 4).unwrap().to_bytes();
+    let with = |at: usize, new: &[u8]| {
+        let mut b = bytes.clone();
+        b[at..at + new.len()].copy_from_slice(new);
+        Bocu1Index::from_bytes(&b)
+    };
+    // A char length so large that the checkpoint count overflows.
+    let mut b = bytes.clone();
+    b[..8].copy_from_slice(&1_u64.to_le_bytes());
+    b[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(
+        Bocu1Index::from_bytes(&b),
+        Err(IndexFormatError::WrongLength)
+    );
+    // Checkpoints are at 24 + 12 * i: an offset, then a state.
+    assert_eq!(
+        with(24, &1_u64.to_le_bytes()),
+        Err(IndexFormatError::BadCheckpoint(0))
+    );
+    assert_eq!(
+        with(32, &0x60_u32.to_le_bytes()),
+        Err(IndexFormatError::BadCheckpoint(0))
+    );
+    assert_eq!(
+        with(36, &0_u64.to_le_bytes()),
+        Err(IndexFormatError::BadCheckpoint(1))
+    );
+    assert_eq!(
+        with(48, &100_u64.to_le_bytes()),
+        Err(IndexFormatError::BadCheckpoint(2))
+    );
+}
+
+#[test]
+fn test_index() {
+    use crate::{write_encoded_chars_with_options, EncoderOptions, ResetPolicy};
+    let s = "hello εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之 ".repeat(5);
+    let plain: Vec<u8> = s.as_str().encode_bocu1().collect();
+    let mut reset = Vec::new();
+    let options = EncoderOptions::new(ResetPolicy::EveryChars(5));
+    write_encoded_chars_with_options(&s, &mut reset, options).unwrap();
+    for v in &[plain, reset] {
+        for &interval in &[0, 1, 3, 7, 64, 1000] {
+            assert!(check_index(&s, v, interval));
+        }
+    }
+    assert!(check_index("", &[], 4));
+    assert!(crate::Bocu1Index::new(&[0xd0], 4).is_err());
+}
+
+#[test]
+fn test_index_random_strings() {
+    use self::quickcheck::*;
+    fn check_one(s: String, interval: u8) -> bool {
+        let v: Vec<u8> = s.as_str().encode_bocu1().collect();
+        check_index(&s, &v, usize::from(interval))
+    }
+    QuickCheck::new()
+        .tests(1_000)
+        .max_tests(1_000)
+        .quickcheck(check_one as fn(String, u8) -> bool);
+}
+
+// Decode `v` taking chars from the front or back of a DoubleEndedDecodeIter
+// as `dirs` directs, and put the result back in order.
+fn decode_both_ends(v: &[u8], dirs: &[bool]) -> String {
+    use crate::DoubleEndedDecodeIter;
+    let mut iter = DoubleEndedDecodeIter::new(v);
+    let mut front = String::new();
+    let mut back = Vec::new();
+    for &forwards in dirs.iter().chain(core::iter::repeat(&false)) {
+        let c = if forwards {
+            iter.next()
+        } else {
+            iter.next_back()
+        };
+        match c {
+            None => break,
+            Some(c) if forwards => front.push(c),
+            Some(c) => back.push(c),
+        }
+    }
+    assert_eq!(iter.next(), None);
+    front.extend(back.iter().rev());
+    front
+}
+
+#[test]
+fn test_double_ended_decode() {
+    use crate::{write_encoded_chars_with_options, EncoderOptions, ResetPolicy};
+    let s = "hello εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之 ".repeat(5);
+    let plain: Vec<u8> = s.as_str().encode_bocu1().collect();
+    let mut reset = Vec::new();
+    let options = EncoderOptions::new(ResetPolicy::EveryBytes(16));
+    write_encoded_chars_with_options(&s, &mut reset, options).unwrap();
+    for v in &[plain, reset] {
+        let rev: String = Bocu1Str::from_bytes(v).unwrap().chars().rev().collect();
+        assert_eq!(rev, s.chars().rev().collect::<String>());
+        for step in 1..5 {
+            let dirs: Vec<bool> = (0..v.len()).map(|i| i % step == 0).collect();
+            assert_eq!(decode_both_ends(v, &dirs), s);
+        }
+    }
+    // Malformed input decodes lossily from either end.
+    let v = [0x50, 0xd0, 0x00, 0x51];
+    let rev: String = crate::DoubleEndedDecodeIter::new(&v).rev().collect();
+    assert_eq!(rev, "\u{1}\u{0}\u{FFFD}\u{0}");
+    assert_eq!(rev, decode_lossy(&v).0.chars().rev().collect::<String>());
+}
+
+#[test]
+fn test_double_ended_decode_random_strings() {
+    use self::quickcheck::*;
+    fn check_one(s: String, dirs: Vec<bool>) -> bool {
+        let v: Vec<u8> = s.as_str().encode_bocu1().collect();
+        decode_both_ends(&v, &dirs) == s
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(String, Vec<bool>) -> bool);
+}
+
+#[test]
+fn test_decode_from() {
+    use crate::{decode_from, write_encoded_chars_with_options, EncoderOptions, ResetPolicy};
+    let s = "學而時習之，不亦說乎？有朋自遠方來，不亦樂乎？ hello\tεφαρμογών ".repeat(10);
+    let mut v = Vec::new();
+    let options = EncoderOptions::new(ResetPolicy::EveryBytes(32));
+    write_encoded_chars_with_options(&s, &mut v, options).unwrap();
+    let (start, iter) = decode_from(&v, 0).unwrap();
+    assert_eq!(start, 0);
+    assert_eq!(iter.collect::<Result<String, _>>().unwrap(), s);
+    for offset in 0..v.len() {
+        // Past the last sync point there is nowhere to start.
+        let Some((start, iter)) = decode_from(&v, offset) else {
+            assert!(v.len() - offset < 32);
+            continue;
+        };
+        assert!(start >= offset && start - offset < 32);
+        let tail = iter.collect::<Result<String, _>>().unwrap();
+        assert!(s.ends_with(&tail));
+    }
+    assert!(decode_from(&v, v.len()).is_none());
+
+    // Errors are reported at their offset in the whole buffer.
+    let v = [0xd0, 0xd0, 0x0a, 0x50, 0xd0];
+    let (start, mut iter) = decode_from(&v, 1).unwrap();
+    assert_eq!(start, 2);
+    assert_eq!(iter.next().unwrap().unwrap(), '\n');
+    assert_eq!(iter.next().unwrap().unwrap(), '\u{0}');
+    assert_eq!(iter.next().unwrap().unwrap_err().offset(), 4);
+}
+
+#[test]
+fn test_delta_coder_state() {
+    use crate::delta_encoding::{normalized_prev, DeltaCoder};
+    use std::collections::HashSet;
+    let mut reachable: HashSet<char> = ('\u{21}'..='\u{10FFFF}').map(normalized_prev).collect();
+    reachable.insert(DeltaCoder::new().state());
+    for c in ('\u{0}'..'\u{20000}').chain('\u{10F000}'..='\u{10FFFF}') {
+        let state = DeltaCoder::from_state(c);
+        assert_eq!(state.is_some(), reachable.contains(&c), "{:?}", c);
+        assert!(state.is_none_or(|s| s.state() == c));
+    }
+    assert!(DeltaCoder::from_state('\u{3040}').is_some());
+    assert!(DeltaCoder::from_state('\u{3070}').is_some());
+    assert!(DeltaCoder::from_state('\u{3071}').is_none());
+}
+
+#[test]
+fn test_resume_from_state() {
+    use crate::buffer::{Decoder, Encoder};
+    use crate::delta_encoding::DeltaCoder;
+    use crate::{DecodeResultIter, EncodedChunkIter};
+    use std::io::{Read, Write};
+    let a = "hello εφαρμογών आजकल\nвоплощению";
+    let b = " HELLOコンニチワ\n學而時習之";
+    let ab: Vec<u8> = format!("{}{}", a, b).as_str().encode_bocu1().collect();
+
+    // The trailing state, however it is arrived at, is saved as a char and
+    // restored from it.
+    let mut enc_a = a.encode_bocu1();
+    let va: Vec<u8> = enc_a.by_ref().collect();
+    let saved = enc_a.state().state();
+    assert_eq!(Bocu1String::from(a).state().state(), saved);
+    let mut iter = DecodeResultIter::new(&va);
+    assert!(iter.by_ref().all(|r| r.is_ok()));
+    assert_eq!(iter.state().state(), saved);
+    let mut inc = IncrementalDecoder::new();
+    assert!(inc.push_slice(&va).all(|r| r.is_ok()));
+    assert_eq!(inc.state().state(), saved);
+    let mut dec = Decoder::new();
+    dec.decode_to_utf8(&va, &mut [0; 256], true);
+    assert_eq!(dec.state().state(), saved);
+    let mut reader = Bocu1Reader::new(&va[..]);
+    reader.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(reader.state().state(), saved);
+    let state = DeltaCoder::from_state(saved).unwrap();
+
+    // Appending from the saved state gives the same bytes as encoding the
+    // whole text at once.
+    let vb: Vec<u8> = EncodedChunkIter::with_state(b.chars(), state)
+        .flat_map(|enc| enc.as_slice().to_vec())
+        .collect();
+    assert_eq!([&va[..], &vb[..]].concat(), ab);
+    let mut w = Bocu1Writer::with_state(va.clone(), state);
+    w.write_all(b.as_bytes()).unwrap();
+    assert_eq!(
+        w.state(),
+        Bocu1String::from_bytes(ab.clone()).unwrap().state()
+    );
+    assert_eq!(w.finish().unwrap(), ab);
+    let mut encoder = Encoder::with_state(state);
+    let mut dst = [0; 256];
+    let (_, _, n) = encoder.encode_from_utf8(b, &mut dst, true);
+    assert_eq!([&va[..], &dst[..n]].concat(), ab);
+}
+
+#[test]
+fn test_concat() {
+    use crate::splice::concat;
+    let parts = [
+        "",
+        "  ",
+        "hello",
+        "εφαρμογών",
+        " आजकल",
+        "\nвоплощению",
+        "  HELLOコンニチワ",
+        "學而時習之",
+        "\u{10FFFF}",
+    ];
+    for a in &parts {
+        for b in &parts {
+            let va: Vec<u8> = a.encode_bocu1().collect();
+            let vb: Vec<u8> = b.encode_bocu1().collect();
+            let ab = format!("{}{}", a, b);
+            let vab: Vec<u8> = ab.as_str().encode_bocu1().collect();
+            assert_eq!(concat(&va, &vb).unwrap(), vab);
+            let mut s = Bocu1String::from(*a);
+            s.push_bocu1(Bocu1Str::from_bytes(&vb).unwrap());
+            assert_eq!(s, Bocu1String::from(ab.as_str()));
+            // The string's state must be right to carry on appending.
+            s.push_str("h學");
+            assert_eq!(s, Bocu1String::from(format!("{}h學", ab).as_str()));
+        }
+    }
+    let e = concat(&[0x50], &[0x20, 0xd0]).unwrap_err();
+    assert_eq!(e.kind(), DecodeErrorKind::TruncatedInput);
+    assert_eq!(e.offset(), 2);
+}
+
+#[test]
+fn test_concat_random_strings() {
+    use self::quickcheck::*;
+    use crate::splice::concat;
+    fn check_one(a: String, b: String) -> bool {
+        let va: Vec<u8> = a.as_str().encode_bocu1().collect();
+        let vb: Vec<u8> = b.as_str().encode_bocu1().collect();
+        let ab = a + &b;
+        concat(&va, &vb).unwrap() == ab.as_str().encode_bocu1().collect::<Vec<u8>>()
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(String, String) -> bool);
+}
+
+#[test]
+fn test_substring() {
+    use crate::splice::substring;
+    use crate::{write_encoded_chars_with_options, EncoderOptions, ResetPolicy};
+    let s = "hello  εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之";
+    let chars: Vec<char> = s.chars().collect();
+    let v: Vec<u8> = s.encode_bocu1().collect();
+    let mut reset = Vec::new();
+    let options = EncoderOptions::new(ResetPolicy::EveryBytes(8));
+    write_encoded_chars_with_options(s, &mut reset, options).unwrap();
+    for a in 0..=chars.len() + 1 {
+        for b in 0..=chars.len() + 1 {
+            let sub: String = chars.iter().skip(a).take(b.saturating_sub(a)).collect();
+            let expected: Vec<u8> = sub.as_str().encode_bocu1().collect();
+            assert_eq!(substring(&v, a..b).unwrap(), expected);
+            // Reset bytes are copied along with the rest.
+            assert_eq!(from_bocu1(&substring(&reset, a..b).unwrap()).unwrap(), sub);
+        }
+    }
+    let e = substring(&[0x50, 0xd0], 1..2).unwrap_err();
+    assert_eq!(e.offset(), 1);
+}
+
+#[test]
+fn test_substring_random_strings() {
+    use self::quickcheck::*;
+    use crate::splice::substring;
+    fn check_one(s: String, a: u8, b: u8) -> bool {
+        let (a, b) = (usize::from(a), usize::from(b));
+        let v: Vec<u8> = s.as_str().encode_bocu1().collect();
+        let sub: String = s.chars().skip(a).take(b.saturating_sub(a)).collect();
+        substring(&v, a..b).unwrap() == sub.as_str().encode_bocu1().collect::<Vec<u8>>()
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(String, u8, u8) -> bool);
+}
+
+// Map arbitrary bytes to a small alphabet, so that random needles and
+// haystacks have plenty of matches.
+fn small_alphabet(v: &[u8]) -> String {
+    let alphabet = ['a', 'b', ' ', '\n', 'é', '學', '習', '\u{10FFFF}'];
+    v.iter()
+        .map(|&b| alphabet[usize::from(b) % alphabet.len()])
+        .collect()
+}
+
+fn check_find(h: &str, needle: &str) -> bool {
+    use crate::search::{contains, find, find_iter, starts_with};
+    let v: Vec<u8> = h.encode_bocu1().collect();
+    let expected: Vec<usize> = h
+        .match_indices(needle)
+        .map(|(i, _)| crate::encoded_len(&h[..i]))
+        .collect();
+    let found: Vec<usize> = find_iter(&v, needle).collect();
+    found == expected
+        && find(&v, needle) == expected.first().copied()
+        && contains(&v, needle) == h.contains(needle)
+        && starts_with(&v, needle) == h.starts_with(needle)
+}
+
+#[test]
+fn test_search() {
+    let h = "hello  εφαρμογών आजकल\nвоплощению HELLOコンニチワ\n學而時習之 hello  學而";
+    for needle in &[
+        "",
+        " ",
+        "  ",
+        "h",
+        "hello",
+        "  ε",
+        "αρμ",
+        "\n",
+        "\nв",
+        "學而",
+        "而",
+        "l",
+        "ll",
+        "hellp",
+        "之 h",
+        "क",
+        "\u{10FFFF}",
+    ] {
+        assert!(check_find(h, needle), "{:?}", needle);
+    }
+    assert!(check_find("", ""));
+    assert!(check_find("", "a"));
+    assert!(check_find("aaaa", "aa"));
+    let s = Bocu1String::from(h);
+    assert_eq!(s.find("HELLO"), crate::search::find(s.as_bytes(), "HELLO"));
+    assert!(s.contains("時習"));
+    assert!(!s.contains("時時"));
+    assert!(s.starts_with("hello  ε"));
+}
+
+#[test]
+fn test_search_random_strings() {
+    use self::quickcheck::*;
+    fn check_one(h: Vec<u8>, needle: Vec<u8>) -> bool {
+        check_find(
+            &small_alphabet(&h),
+            &small_alphabet(&needle[..needle.len().min(4)]),
+        )
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(Vec<u8>, Vec<u8>) -> bool);
+}
+
+fn in_prefix_range(prefix: &str, s: &str) -> bool {
+    use crate::ordering::prefix_range;
+    use std::ops::{Bound, RangeBounds};
+    let v: Vec<u8> = s.encode_bocu1().collect();
+    let (lo, hi) = prefix_range(prefix);
+    (Bound::Included(lo), hi).contains(&v)
+}
+
+#[test]
+fn test_prefix_range() {
+    use crate::ordering::prefix_range;
+    use std::ops::Bound;
+    assert_eq!(prefix_range(""), (vec![], Bound::Excluded(vec![0xff])));
+    for prefix in &["", "a", "hello ", "학", "\n", "學而", "\u{10FFFF}"] {
+        for s in &[
+            "",
+            "a",
+            "b",
+            "hello",
+            "hello world",
+            "학교",
+            "學而時習之",
+            "\n",
+            "\u{10FFFF}",
+        ] {
+            let extended = format!("{}{}", prefix, s);
+            assert!(in_prefix_range(prefix, &extended));
+            assert_eq!(in_prefix_range(prefix, s), s.starts_with(prefix));
+        }
+        // The upper bound is tight: the largest next char has the lead byte
+        // just below it.
+        let (lo, hi) = prefix_range(prefix);
+        let max: Vec<u8> = format!("{}\u{10FFFF}", prefix)
+            .as_str()
+            .encode_bocu1()
+            .collect();
+        assert_eq!(
+            hi,
+            Bound::Excluded([&lo[..], &[max[lo.len()] + 1]].concat())
+        );
+    }
+}
+
+#[test]
+fn test_prefix_range_random_extensions() {
+    use self::quickcheck::*;
+    fn check_one(prefix: String, rest: String) -> bool {
+        in_prefix_range(&prefix, &(prefix.clone() + &rest))
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(String, String) -> bool);
+}
+
+#[test]
+fn test_prefix_range_random_strings() {
+    use self::quickcheck::*;
+    // Strings from a small alphabet often share part of the prefix, or run
+    // to either side of it in order.
+    fn check_one(prefix: Vec<u8>, s: Vec<u8>) -> bool {
+        let prefix = small_alphabet(&prefix);
+        let s = small_alphabet(&s);
+        in_prefix_range(&prefix, &s) == s.starts_with(&prefix)
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(Vec<u8>, Vec<u8>) -> bool);
+}
+
+// Check that splitting the encoding of `s` gives the encodings of the pieces
+// str would split it into.
+fn check_split(s: &str) -> bool {
+    use crate::split::{lines, split_c0};
+    let encode = |s: &str| s.encode_bocu1().collect::<Vec<u8>>();
+    let v = encode(s);
+    let expected: Vec<Vec<u8>> = s.lines().map(encode).collect();
+    let ok = lines(&v).map(<[u8]>::to_vec).collect::<Vec<_>>() == expected;
+    ok && [0x0a_u8, 0x01, 0x1f].iter().all(|&sep| {
+        let expected: Vec<Vec<u8>> = s.split(char::from(sep)).map(encode).collect();
+        split_c0(&v, sep).map(<[u8]>::to_vec).collect::<Vec<_>>() == expected
+    })
+}
+
+#[test]
+fn test_split() {
+    assert!(check_split(""));
+    assert!(check_split("\n"));
+    assert!(check_split("a\r\nb\n\nc\r\n"));
+    assert!(check_split(
+        "hello εφαρμογών आजकल\nвоплощению\u{1}HELLOコンニチワ\n學而時習之\u{1f}"
+    ));
+    // A separator that is also a trailing byte is only matched at the start
+    // of a code unit: U+0080 from the initial state is D0 01.
+    let v: Vec<u8> = "\u{80}\u{1}x".encode_bocu1().collect();
+    assert_eq!(&v[..2], &[0xd0, 0x01]);
+    assert_eq!(crate::split::split_c0(&v, 0x01).count(), 2);
+}
+
+#[test]
+fn test_split_random_strings() {
+    use self::quickcheck::*;
+    fn check_one(v: Vec<u8>) -> bool {
+        let alphabet = [
+            'a',
+            '\n',
+            '\r',
+            '\u{1}',
+            '\u{1f}',
+            'é',
+            'Ă',
+            '學',
+            '\u{10FFFF}',
+        ];
+        let s: String = v
+            .iter()
+            .map(|&b| alphabet[usize::from(b) % alphabet.len()])
+            .collect();
+        check_split(&s)
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(Vec<u8>) -> bool);
+}
+
+#[test]
+fn test_packed_str() {
+    use crate::packed::PackError;
+    use crate::PackedStr;
+    use core::convert::TryFrom;
+    let hello = PackedStr::<u64>::try_from("hello").unwrap();
+    assert_eq!(hello.to_scalar(), 0x_b8_b5_bc_bc__bf_00_00_00_u64);
+    assert_eq!(&*hello.as_bytes(), &[0xb8, 0xb5, 0xbc, 0xbc, 0xbf]);
+    assert_eq!(hello.len(), 5);
+    assert!(!hello.is_empty());
+    assert_eq!(hello.to_string(), "hello");
+    assert_eq!(format!("{:?}", hello), "\"hello\"");
+    let empty = PackedStr::<u64>::try_from("").unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(empty.len(), 0);
+    assert!(empty < hello);
+    assert_eq!(
+        PackedStr::<u64>::try_from("hellowor!"),
+        Err(PackError::TooLong)
+    );
+    let greek = PackedStr::<u128>::try_from("εφαρμογών").unwrap();
+    assert_eq!(greek.to_string(), "εφαρμογών");
+    assert_eq!(greek.len(), 10);
+    assert_eq!(
+        format!("{:?}", PackedStr::<u128>::try_from("a\tb").unwrap()),
+        "\"a\\tb\""
+    );
+}
+
+#[test]
+fn test_packed_str_order_random_strings() {
+    use self::quickcheck::*;
+    use crate::PackedStr;
+    use core::convert::TryFrom;
+    fn check_one(s1: String, s2: String) -> bool {
+        // NULs are not represented in packed strings, so leave them out.
+        let s1: String = s1.chars().filter(|&c| c != '\0').take(4).collect();
+        let s2: String = s2.chars().filter(|&c| c != '\0').take(4).collect();
+        let p1 = PackedStr::<u128>::try_from(s1.as_str()).unwrap();
+        let p2 = PackedStr::<u128>::try_from(s2.as_str()).unwrap();
+        p1.cmp(&p2) == s1.cmp(&s2) && p1.to_string() == s1 && (p1 == p2) == (s1 == s2)
+    }
+    QuickCheck::new()
+        .tests(10_000)
+        .max_tests(10_000)
+        .quickcheck(check_one as fn(String, String) -> bool);
+}
+
+#[test]
+fn test_pack_nul() {
+    use crate::packed::{pack_escaped, PackError};
+    assert_eq!(try_pack::<_, u64>(&"a\0b"), Err(PackError::ContainsNul));
+    assert_eq!(try_pack::<_, u64>(&"ab\0"), Err(PackError::ContainsNul));
+    assert_eq!(try_pack::<_, u64>(&"hellowor!"), Err(PackError::TooLong));
+
+    // A zero byte inside a plain packed string is an error, not skipped.
+    let p: u64 = 0x_b8_00_b5_00__00_00_00_00_u64;
+    let res: Vec<_> = DecodePackedResultIter::new(p).collect();
+    assert_eq!(res.len(), 3);
+    assert_eq!(res[0], Ok('h'));
+    let e = res[1].unwrap_err();
+    assert_eq!(e.kind(), DecodeErrorKind::BadPackedByte(0));
+    assert_eq!(e.offset(), 1);
+    assert_eq!(res[2], Ok('e'));
+
+    // The escaped layout holds NULs, and agrees with the plain one on
+    // strings without them.
+    let p: u64 = pack_escaped(&"a\0b").unwrap();
+    assert_eq!(p, 0x_b1_01_01_b2__00_00_00_00_u64);
+    let u: String = p.decode_escaped_packed_bocu1().collect();
+    assert_eq!(u, "a\0b");
+    let p: u64 = pack_escaped(&"ab\0").unwrap();
+    let u: String = p.decode_escaped_packed_bocu1().collect();
+    assert_eq!(u, "ab\0");
+    assert!(pack_escaped::<_, u64>(&"ab").unwrap() < p);
+    assert_eq!(pack_escaped::<_, u64>(&"hello"), try_pack(&"hello"));
+    assert_eq!(
+        pack_escaped::<_, u64>(&"hello\0\0"),
+        Err(PackError::TooLong)
+    );
+
+    // An escape byte must be followed by 0x01 or 0x02.
+    for &(p, bad) in &[
+        (0x_b1_01_03_00__00_00_00_00_u64, 0x03),
+        (0x_b1_01_00_00__00_00_00_00_u64, 0x00),
+        (0x_b1_b1_b1_b1__b1_b1_b1_01_u64, 0x00),
+    ] {
+        let e = DecodePackedResultIter::new_escaped(p)
+            .find_map(Result::err)
+            .expect("expected a decode error");
+        assert_eq!(e.kind(), DecodeErrorKind::BadPackedByte(bad));
+    }
+}
+
+#[test]
+fn test_lex_order_random_escaped_packed_string_pairs() {
+    use self::quickcheck::*;
+    use crate::packed::{pack_escaped, PackError};
+    fn check_two(s1: String, s2: String) -> bool {
+        // Turn some chars into NULs so that there are plenty of them, and
+        // take few enough chars that they nearly always fit escaped.
+        let nuls = |s: String| -> String {
+            s.chars()
+                .map(|c| if c as u32 % 4 == 0 { '\0' } else { c })
+                .take(3)
+                .collect()
+        };
+        let (s1, s2) = (nuls(s1), nuls(s2));
+        let (p1, p2): (u128, u128) = match (pack_escaped(&s1.as_str()), pack_escaped(&s2.as_str()))
+        {
+            (Ok(p1), Ok(p2)) => (p1, p2),
+            (Err(PackError::TooLong), _) | (_, Err(PackError::TooLong)) => return true,
+            _ => return false,
+        };
+        let u1: String = p1.decode_escaped_packed_bocu1().collect();
+        p1.cmp(&p2) == s1.cmp(&s2) && u1 == s1
+    }
+    QuickCheck::new()
+        .tests(50_000)
+        .max_tests(50_000)
+        .quickcheck(check_two as fn(String, String) -> bool);
+}
+
+#[test]
+fn test_key() {
+    use crate::Bocu1Key;
+    let short = Bocu1Key::from("hello");
+    let long = Bocu1Key::from("hello, this is a longer key");
+    let nul = Bocu1Key::from("hello\0");
+    assert!(short.is_inline());
+    assert!(!long.is_inline());
+    assert!(!nul.is_inline());
+    assert_eq!(
+        short.as_packed().unwrap().to_scalar() >> 88,
+        0xb8_b5_bc_bc_bf
+    );
+    assert_eq!(short.len(), 5);
+    assert_eq!(long.to_string(), "hello, this is a longer key");
+    assert_eq!(format!("{:?}", nul), "\"hello\\0\"");
+    assert!(short < nul && nul < long);
+    assert!(Bocu1Key::from("") < short && Bocu1Key::from("").is_empty());
+
+    // However a key is made, the same string gets the same representation.
+    for s in &["hello", "hello, this is a longer key", "hello\0"] {
+        let k = Bocu1Key::from(*s);
+        let b = Bocu1String::from(*s);
+        assert_eq!(Bocu1Key::from(b.as_bocu1_str()), k);
+        assert_eq!(Bocu1Key::from(b.clone()), k);
+        assert_eq!(k.to_bocu1_string(), b);
+    }
+
+    // Encodings with reset bytes in them make the same keys as without.
+    let h = Bocu1String::from_bytes(vec![0xff, 0xb8]).unwrap();
+    assert_eq!(Bocu1Key::from(h.as_bocu1_str()), Bocu1Key::new("h"));
+    assert_eq!(Bocu1Key::from(h.clone()), Bocu1Key::new("h"));
+    assert!(Bocu1Key::from(h) < Bocu1Key::new("ha"));
+    let mut v: Vec<u8> = "hello, this is a longer key".encode_bocu1().collect();
+    v.splice(5..5, [0xff; 4]);
+    let long = Bocu1String::from_bytes(v).unwrap();
+    assert_eq!(
+        Bocu1Key::from(long.as_bocu1_str()),
+        Bocu1Key::new("hello, this is a longer key")
+    );
+    assert_eq!(
+        Bocu1Key::from(long).cmp(&Bocu1Key::new("hello, this is a longer kez")),
+        core::cmp::Ordering::Less
+    );
+}
+
+#[test]
+fn test_key_order_random_strings() {
+    use self::quickcheck::*;
+    use crate::Bocu1Key;
+    fn check_two(s1: String, s2: String) -> bool {
+        // Mix short keys with long ones sharing their prefixes, and with
+        // some NULs, so that every pairing of representations turns up.
+        let vary = |s: String| -> String {
+            let n = s.chars().count();
+            s.chars()
+                .map(|c| if c as u32 % 7 == 0 { '\0' } else { c })
+                .take(if n % 2 == 0 { 3 } else { 20 })
+                .collect()
+        };
+        let (s1, s2) = (vary(s1), vary(s2));
+        let (k1, k2) = (Bocu1Key::from(s1.as_str()), Bocu1Key::from(s2.as_str()));
+        k1.cmp(&k2) == s1.cmp(&s2) && (k1 == k2) == (s1 == s2) && k1.to_string() == s1
+    }
+    QuickCheck::new()
+        .tests(50_000)
+        .max_tests(50_000)
+        .quickcheck(check_two as fn(String, String) -> bool);
+    // Pairs with a common prefix, one inline and one not, are the case that
+    // needs the tie-break.
+    assert!(check_two(
+        "abc".to_string(),
+        "abcdefghijklmnopq".to_string()
+    ));
+    assert!(check_two(
+        "abcdefghijklmnopq".to_string(),
+        "abc".to_string()
+    ));
+}
+
+#[test]
+fn test_packed_neighbours_exhaustive_u16() {
+    use crate::packed::{packed_predecessor, packed_successor};
+    // Every packed string that fits in a u16, in order.
+    let valid: Vec<u16> = (0..=u16::MAX)
+        .filter(|&n| {
+            let s: Result<String, _> = DecodePackedResultIter::new(n).collect();
+            s.is_ok_and(|s| pack(&s.as_str()) == Some(n))
+        })
+        .collect();
+    assert_eq!(valid[0], 0);
+    assert_eq!(packed_predecessor(valid[0]), None);
+    assert_eq!(packed_successor(valid[valid.len() - 1]), None);
+    for w in valid.windows(2) {
+        assert_eq!(packed_successor(w[0]), Some(w[1]));
+        assert_eq!(packed_predecessor(w[1]), Some(w[0]));
+    }
+    // Scalars that aren't packed strings have no neighbours.
+    assert_eq!(packed_successor(0x_b8_ff_u16), None);
+    assert_eq!(packed_predecessor(0x_00_b8_u16), None);
+}
+
+#[test]
+fn test_packed_neighbours_random_strings() {
+    use self::quickcheck::*;
+    use crate::packed::{packed_predecessor, packed_successor};
+    fn check_one(s: String) -> bool {
+        let s: String = s.chars().filter(|&c| c != '\0').take(4).collect();
+        let n: u128 = pack(&s.as_str()).unwrap();
+        let Some(p): Option<u32> = pack(&s.as_str()) else {
+            return true;
+        };
+        let cmp_to = |m: Option<u128>, t: &str| {
+            let u: String = m.unwrap().decode_packed_bocu1().collect();
+            u.as_str().cmp(t)
+        };
+        let succ = packed_successor(n);
+        let pred = packed_predecessor(n);
+        // The neighbours of a string are its neighbours in string order,
+        // and are each other's neighbours.
+        cmp_to(succ, &s) == std::cmp::Ordering::Greater
+            && (s.is_empty() || cmp_to(pred, &s) == std::cmp::Ordering::Less)
+            && packed_predecessor(succ.unwrap()) == Some(n)
+            && pred.is_none_or(|m| packed_successor(m) == Some(n))
+            && packed_successor(p).is_none_or(|m| packed_predecessor(m) == Some(p))
+            && packed_predecessor(p).is_none_or(|m| packed_successor(m) == Some(p))
+    }
+    QuickCheck::new()
+        .tests(20_000)
+        .max_tests(20_000)
+        .quickcheck(check_one as fn(String) -> bool);
+}
+
+#[test]
+fn test_packed_prefix_range() {
+    use crate::packed::packed_prefix_range;
+    use std::collections::BTreeMap;
+    let words = [
+        "",
+        "he",
+        "hello",
+        "help",
+        "helm",
+        "hem",
+        "h\u{10FFFF}",
+        "hé",
+        "he\u{7f}",
+        "he学",
+        "i",
+        "heading",
+    ];
+    let map: BTreeMap<u64, &str> = words.iter().map(|&w| (pack(&w).unwrap(), w)).collect();
+    for prefix in &["", "h", "he", "hel", "hello", "x", "he学"] {
+        let range = packed_prefix_range::<u64>(prefix).unwrap();
+        let found: Vec<&str> = map.range(range).map(|(_, &w)| w).collect();
+        let mut expected: Vec<&str> = words
+            .iter()
+            .copied()
+            .filter(|w| w.starts_with(prefix))
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(found, expected, "prefix {:?}", prefix);
+    }
+    // The bounds are themselves packed strings with the prefix.
+    let range = packed_prefix_range::<u64>("he").unwrap();
+    let hi: String = range.end().decode_packed_bocu1().collect();
+    assert!(hi.starts_with("he") && hi.len() > 2);
+    assert_eq!(*range.start(), pack(&"he").unwrap());
+    assert_eq!(
+        packed_prefix_range::<u64>("hellowor"),
+        Some(pack(&"hellowor").unwrap()..=pack(&"hellowor").unwrap())
+    );
+    assert_eq!(packed_prefix_range::<u64>("hellowor!"), None);
+    assert_eq!(packed_prefix_range::<u64>("a\0"), None);
+}
+
+#[test]
+fn test_packed_prefix_range_random_strings() {
+    use self::quickcheck::*;
+    use crate::packed::packed_prefix_range;
+    fn check_two(prefix: String, s: String) -> bool {
+        let prefix: String = prefix.chars().filter(|&c| c != '\0').take(2).collect();
+        let s: String = s.chars().filter(|&c| c != '\0').take(2).collect();
+        let range = packed_prefix_range::<u128>(&prefix).unwrap();
+        // Try the string both with and without the prefix.
+        [s.clone(), prefix.clone() + &s].iter().all(|t| {
+            let n: u128 = pack(&t.as_str()).unwrap();
+            range.contains(&n) == t.starts_with(prefix.as_str())
+        })
+    }
+    QuickCheck::new()
+        .tests(20_000)
+        .max_tests(20_000)
+        .quickcheck(check_two as fn(String, String) -> bool);
+}
+
+#[test]
+fn test_pack_prefix() {
+    use crate::packed::{pack_bytes, pack_prefix, PackError};
+    assert_eq!(pack_prefix::<u32>("hello"), (0x_b8_b5_bc_bc_u32, 4));
+    assert_eq!(pack_prefix::<u64>("hello"), (pack(&"hello").unwrap(), 5));
+    assert_eq!(pack_prefix::<u64>(""), (0, 0));
+    assert_eq!(
+        pack_prefix::<u64>("a\0b"),
+        (0x_b1_00_b2_00__00_00_00_00_u64, 3)
+    );
+
+    // A code unit that doesn't fit whole is packed as far as it goes, but
+    // not counted.
+    let enc: Vec<u8> = "hel學".encode_bocu1().collect();
+    assert_eq!(enc.len(), 6);
+    let (n, count) = pack_prefix::<u32>("hel學");
+    assert_eq!(n, u32::from_be_bytes([enc[0], enc[1], enc[2], enc[3]]));
+    assert_eq!(count, 3);
+
+    assert_eq!(pack_bytes::<u64>(&enc), try_pack(&"hel學"));
+    assert_eq!(pack_bytes::<u32>(&enc), Err(PackError::TooLong));
+    assert_eq!(
+        pack_bytes::<u64>(&[0xb1, 0x00]),
+        Err(PackError::ContainsNul)
+    );
+}
+
+#[test]
+fn test_pack_prefix_random_strings() {
+    use self::quickcheck::*;
+    use crate::packed::{pack_bytes, pack_prefix};
+    fn check_two(s1: String, s2: String) -> bool {
+        let (k1, c1) = pack_prefix::<u64>(&s1);
+        let (k2, _) = pack_prefix::<u64>(&s2);
+        // Unequal keys order the strings; equal ones say nothing.
+        let ordered = k1 == k2 || k1.cmp(&k2) == s1.cmp(&s2);
+        // The key is the first 8 bytes of the encoding, of which the first
+        // c1 chars' code units are all there, and the next one's are not.
+        let enc: Vec<u8> = s1.as_str().encode_bocu1().collect();
+        let mut first = enc.clone();
+        first.resize(8, 0);
+        let whole: String = s1.chars().take(c1).collect();
+        let whole_len = whole.as_str().encode_bocu1().count();
+        let next: String = s1.chars().take(c1 + 1).collect();
+        let next_len = next.as_str().encode_bocu1().count();
+        let exact = c1 < s1.chars().count() || s1.contains('\0') || pack_bytes(&enc) == Ok(k1);
+        ordered
+            && k1.to_be_bytes() == first[..8]
+            && whole_len <= 8
+            && (c1 == s1.chars().count() || next_len > 8)
+            && exact
+    }
+    QuickCheck::new()
+        .tests(20_000)
+        .max_tests(20_000)
+        .quickcheck(check_two as fn(String, String) -> bool);
+}
+
 #[test]
 fn test_pack64() {
     let p: u64 = pack(&"hello").unwrap();
@@ -14,7 +14,7 @@
 //! the output byte stream when the same-numbered unicode scalars were present
 //! in the input text.
 //!
-use crate::DecodeError;
+use crate::DecodeErrorKind;
 
 // BOCU-1 avoids using 13 values for trailing bytes in a multibyte code
 // unit, leaving 256 - 13 = 243 values.
@@ -58,13 +58,13 @@ pub fn trail_to_byte(b: u8) -> u8 {
 /// Inverse of the mapping in trail_to_byte above, returning None for
 /// inputs that are outside the output range of trail_to_byte.
 #[inline]
-pub fn byte_to_trail(b: u8) -> Result<u8, DecodeError> {
+pub fn byte_to_trail(b: u8) -> Result<u8, DecodeErrorKind> {
     let v = match b {
         0x01..=0x06 => Ok(b - 1),
         0x10..=0x19 => Ok((b - 1) - 9),
         0x1C..=0x1F => Ok(((b - 1) - 9) - 2),
         0x21..=0xFF => Ok((((b - 1) - 9) - 2) - 1),
-        _ => Err(DecodeError::TrailByteOutOfRange(b)),
+        _ => Err(DecodeErrorKind::TrailByteOutOfRange(b)),
     };
     match v {
         Err(_) => trace!("TrailingByteSelection:byte_to_trail(0x{:x}) => Err", b),
@@ -76,3 +76,16 @@ pub fn byte_to_trail(b: u8) -> Result<u8, DecodeError> {
     }
     v
 }
+
+/// Whether a byte unambiguously marks a point where decoding can restart.
+///
+/// Every C0 control resets the delta-coder state, and 0xFF does too when it
+/// appears as a lead byte, but most of those byte values are _also_ produced
+/// by trail_to_byte above. Only the excluded codes are guaranteed never to
+/// occur inside a multibyte code unit, so only they (minus SP, which leaves
+/// the state alone) can be trusted as sync points when scanning bytes whose
+/// code unit boundaries are unknown.
+#[inline]
+pub fn is_sync_byte(b: u8) -> bool {
+    b != 0x20 && EXCLUDED_CODE_BYTES.contains(&b)
+}
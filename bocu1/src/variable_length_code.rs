@@ -80,7 +80,8 @@ const_assert_eq!(assert_H3D; HI_3BYTE_DELTA,  0x0002_DD0B);
 use crate::trailing_byte_selection;
 use crate::trailing_byte_selection::N_TRAIL_VALUES;
 use crate::util::Euc;
-use crate::{DecodeError, EncodedChunk};
+use crate::{DecodeErrorKind, EncodedChunk};
+use core::ops::RangeInclusive;
 
 #[inline]
 #[allow(clippy::cast_sign_loss)]
@@ -149,9 +150,35 @@ pub fn encode_delta(delta: i32) -> EncodedChunk {
     }
 }
 
+/// The length of the code encode_delta would produce for `delta`, computed
+/// from the delta ranges alone without choosing any of the bytes.
+#[inline]
+pub fn encoded_delta_len(delta: i32) -> usize {
+    match delta {
+        LO_1BYTE_DELTA..=HI_1BYTE_DELTA => 1,
+        LO_2BYTE_DELTA..=HI_2BYTE_DELTA => 2,
+        LO_3BYTE_DELTA..=HI_3BYTE_DELTA => 3,
+        _ => 4,
+    }
+}
+
+/// The deltas encode_delta codes in at most `len` bytes: the inverse of
+/// encoded_delta_len. Every delta between two chars fits in 4.
+pub fn delta_range_within_len(len: usize) -> RangeInclusive<i32> {
+    match len {
+        0 => RangeInclusive::new(1, 0),
+        1 => LO_1BYTE_DELTA..=HI_1BYTE_DELTA,
+        2 => LO_2BYTE_DELTA..=HI_2BYTE_DELTA,
+        3 => LO_3BYTE_DELTA..=HI_3BYTE_DELTA,
+        _ => i32::MIN..=i32::MAX,
+    }
+}
+
 // The leading byte 0xFF is reserved as a non-coding delta-state-reset byte
 // that applications can inject to get more self-syncronization in the code
 // stream, if they're not seeing enough naturally occurring from C0 codes).
+// Since 0xFF is also a valid trailing byte, a lone one is only recognizable
+// as a reset in certain contexts; see the sync module.
 pub const LEAD_BYTE_RESET: u8 = 0xff;
 
 // Lead-bytes are supposed to be greater than this bytes; bytes at-or-below
@@ -160,7 +187,7 @@ pub const LEAD_BYTE_ASCII_SP: u8 = 0x20;
 
 #[inline]
 #[allow(clippy::needless_range_loop)] // The loop is not "needless" here!
-pub fn decode_delta(b: &[u8]) -> Result<(i32, &[u8]), DecodeError> {
+pub fn decode_delta(b: &[u8]) -> Result<(i32, &[u8]), DecodeErrorKind> {
     assert!(!b.is_empty());
 
     let lead: u8 = b[0];
@@ -182,7 +209,7 @@ pub fn decode_delta(b: &[u8]) -> Result<(i32, &[u8]), DecodeError> {
     };
 
     if b.len() < len {
-        return Err(DecodeError::TruncatedInput);
+        return Err(DecodeErrorKind::TruncatedInput);
     }
 
     let mut delta: i32 = i32::from(lead) - i32::from(base);
@@ -193,3 +220,17 @@ pub fn decode_delta(b: &[u8]) -> Result<(i32, &[u8]), DecodeError> {
     delta += offset;
     Ok((delta, &b[len..]))
 }
+
+/// The length in bytes of the code unit that begins with the byte `lead`,
+/// according to the same lead-byte ranges as decode_delta above. Bytes that
+/// are not delta lead bytes at all (the self-encoded C0 controls and SP, and
+/// LEAD_BYTE_RESET) are complete one-byte code units.
+#[inline]
+pub fn code_unit_len(lead: u8) -> usize {
+    match lead {
+        0x21 | 0xFE => 4,
+        0x22..=0x24 | 0xFB..=0xFD => 3,
+        0x25..=0x4F | 0xD0..=0xFA => 2,
+        _ => 1,
+    }
+}
@@ -1,4 +1,6 @@
 #![allow(dead_code)]
+#![allow(clippy::redundant_field_names)] // Matches the style of the bocu1 crate.
+#![allow(clippy::needless_arbitrary_self_type)]
 
 //! This crate exists just to provide a testing interface
 //! between the IBM reference implementation (in C) and
@@ -93,9 +95,35 @@ impl<'a> RefImplEncodeBOCU1 for &'a str {
     }
 }
 
-struct RefImplDecoder {
+pub struct RefImplDecoder {
     rx: Bocu1Rx,
 }
 
+#[allow(clippy::new_without_default)]
+impl RefImplDecoder {
+    pub fn new() -> RefImplDecoder {
+        RefImplDecoder {
+            rx: Bocu1Rx {
+                prev: 0,
+                count: 0,
+                diff: 0,
+            },
+        }
+    }
+
+    /// Feed one byte to the C decoder, returning the char it completes (if
+    /// any), or the C decoder's negative error code.
+    pub fn push(self: &mut Self, b: u8) -> Result<Option<char>, int32_t> {
+        let c = unsafe { decodeBocu1(&mut self.rx, b) };
+        if c == -1 {
+            Ok(None)
+        } else if c < 0 {
+            Err(c)
+        } else {
+            Ok(::std::char::from_u32(c as u32))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests;
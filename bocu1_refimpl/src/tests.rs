@@ -1,5 +1,8 @@
-use crate::RefImplEncodeBOCU1;
-use bocu1::EncodeBOCU1;
+// A lint that arrived after these tests were written.
+#![allow(clippy::needless_borrow)]
+
+use crate::{RefImplDecoder, RefImplEncodeBOCU1};
+use bocu1::{EncodeBOCU1, IncrementalDecoder};
 extern crate quickcheck;
 
 fn check_conforming(s: &str) {
@@ -36,3 +39,23 @@ fn test_100k_random_strings() {
         .max_tests(100_000)
         .quickcheck(check_one as fn(String) -> bool)
 }
+
+#[test]
+fn test_100k_random_strings_incremental_decode() {
+    use self::quickcheck::*;
+    fn check_one(s: String) -> bool {
+        let v: Vec<u8> = s.as_str().encode_bocu1().collect();
+        let mut refimpl = RefImplDecoder::new();
+        let mut ours = IncrementalDecoder::new();
+        for b in v {
+            if refimpl.push(b) != Ok(ours.push(b).ok().and_then(|c| c)) {
+                return false;
+            }
+        }
+        !ours.is_pending()
+    }
+    QuickCheck::new()
+        .tests(100_000)
+        .max_tests(100_000)
+        .quickcheck(check_one as fn(String) -> bool);
+}